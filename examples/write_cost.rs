@@ -0,0 +1,29 @@
+//! Time `EventFD::write` and `read` with whichever backend is enabled, e.g.
+//!
+//!     cargo run --release --example write_cost
+//!     cargo run --release --example write_cost --no-default-features --features backend-libc
+
+use eventfd::{EventFD, Flags};
+use std::time::Instant;
+
+const ITERS: u32 = 1_000_000;
+
+fn main() {
+    let efd = EventFD::new(0, Flags::EFD_NONBLOCK).expect("eventfd");
+
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        efd.write(1).expect("write");
+    }
+    let write = start.elapsed() / ITERS;
+
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        efd.write(1).expect("write");
+        efd.read().expect("read");
+    }
+    let write_read = start.elapsed() / ITERS;
+
+    println!("write: {:?}/op", write);
+    println!("write+read: {:?}/op", write_read);
+}
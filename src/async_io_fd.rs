@@ -0,0 +1,97 @@
+//! async-io (smol) integration, enabled with the `async-io` feature.
+
+use crate::{EventFD, Mode};
+
+use async_io::Async;
+use std::future::Future;
+use std::io;
+
+impl<M: Mode> EventFD<M> {
+    /// Register with the async-io reactor, switching the fd to non-blocking
+    /// mode first. Like `Async::new`, this affects every clone of the
+    /// EventFD, since they share a file description.
+    pub fn into_async(mut self) -> io::Result<Async<EventFD<M>>> {
+        self.set_nonblocking(true)?;
+        Async::new_nonblocking(self)
+    }
+}
+
+/// Async read and write for an EventFD registered with async-io.
+pub trait AsyncIoExt {
+    /// Read the current value, waiting until it is non-zero. See
+    /// `EventFD::read` for the semaphore-mode semantics.
+    fn read_async(&self) -> impl Future<Output = io::Result<u64>> + Send + '_;
+
+    /// Add to the current value, waiting while the addition would
+    /// overflow the counter.
+    fn write_async(&self, val: u64) -> impl Future<Output = io::Result<()>> + Send + '_;
+}
+
+impl<M: Mode> AsyncIoExt for Async<EventFD<M>> {
+    fn read_async(&self) -> impl Future<Output = io::Result<u64>> + Send + '_ {
+        self.read_with(|efd| Ok(efd.read()?))
+    }
+
+    fn write_async(&self, val: u64) -> impl Future<Output = io::Result<()>> + Send + '_ {
+        self.write_with(move |efd| Ok(efd.write(val)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncIoExt;
+    use crate::{EventFD, Flags};
+    use async_io::{block_on, Async};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_async_io() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let writer = efd.clone();
+        let efd = efd.into_async().unwrap();
+
+        block_on(async {
+            efd.write_async(2).await.unwrap();
+            assert_eq!(efd.read_async().await.unwrap(), 2);
+
+            let t = thread::spawn(move || writer.write(5).unwrap());
+            assert_eq!(efd.read_async().await.unwrap(), 5);
+            t.join().unwrap();
+        });
+    }
+
+    /// Await a read on a zero counter until another thread writes to it.
+    fn read_from_thread(efd: Async<EventFD>, writer: EventFD) {
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            writer.write(3).unwrap();
+        });
+        let mut read = pin!(efd.read_async());
+        let mut cx = Context::from_waker(Waker::noop());
+        // Pending, rather than blocking the executor thread until the write.
+        assert!(read.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(block_on(read).unwrap(), 3);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_new() {
+        let efd = Async::new(EventFD::new(4, Flags::empty()).unwrap()).unwrap();
+        assert_eq!(block_on(efd.read_async()).unwrap(), 4);
+
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let writer = efd.clone();
+        read_from_thread(Async::new(efd).unwrap(), writer);
+    }
+
+    #[test]
+    fn test_into_async_wait() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let writer = efd.clone();
+        read_from_thread(efd.into_async().unwrap(), writer);
+    }
+}
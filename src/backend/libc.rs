@@ -0,0 +1,125 @@
+use super::{Backend, PollFd, SysResult};
+use crate::Flags;
+
+use std::io;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd};
+
+/// Syscalls made directly through libc.
+pub(crate) enum Libc {}
+
+/// Turn a -1 return into the current errno.
+fn check(ret: libc::c_int) -> SysResult<libc::c_int> {
+    if ret == -1 {
+        Err(io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::EIO))
+    } else {
+        Ok(ret)
+    }
+}
+
+fn check_size(ret: libc::ssize_t) -> SysResult<usize> {
+    if ret < 0 {
+        Err(io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::EIO))
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// Take ownership of an fd the kernel just returned.
+fn owned(fd: libc::c_int) -> OwnedFd {
+    // Nothing else has seen this fd yet.
+    unsafe { OwnedFd::from_raw_fd(fd) }
+}
+
+fn getfl(fd: BorrowedFd<'_>) -> SysResult<libc::c_int> {
+    check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) })
+}
+
+impl Backend for Libc {
+    fn eventfd(initval: u32, flags: Flags) -> SysResult<OwnedFd> {
+        check(unsafe { libc::eventfd(initval, flags.bits()) }).map(owned)
+    }
+
+    fn read(fd: BorrowedFd<'_>, buf: &mut [u8]) -> SysResult<usize> {
+        check_size(unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) })
+    }
+
+    fn write(fd: BorrowedFd<'_>, buf: &[u8]) -> SysResult<usize> {
+        check_size(unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr().cast(), buf.len()) })
+    }
+
+    fn dup(fd: BorrowedFd<'_>, cloexec: bool) -> SysResult<OwnedFd> {
+        let ret = if cloexec {
+            unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) }
+        } else {
+            unsafe { libc::dup(fd.as_raw_fd()) }
+        };
+        check(ret).map(owned)
+    }
+
+    fn close(fd: OwnedFd) -> SysResult<()> {
+        check(unsafe { libc::close(fd.into_raw_fd()) }).map(drop)
+    }
+
+    fn poll(fds: &mut [PollFd], timeout: i32) -> SysResult<usize> {
+        let mut pfds: Vec<libc::pollfd> = fds
+            .iter()
+            .map(|p| libc::pollfd {
+                fd: p.fd,
+                events: p.events,
+                revents: 0,
+            })
+            .collect();
+        let n =
+            check(unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, timeout) })?;
+        for (p, pfd) in fds.iter_mut().zip(&pfds) {
+            p.revents = pfd.revents;
+        }
+        Ok(n as usize)
+    }
+
+    fn nonblocking(fd: BorrowedFd<'_>) -> SysResult<bool> {
+        Ok(getfl(fd)? & libc::O_NONBLOCK != 0)
+    }
+
+    fn set_nonblocking(fd: BorrowedFd<'_>, nonblocking: bool) -> SysResult<()> {
+        let fl = getfl(fd)?;
+        let fl = if nonblocking {
+            fl | libc::O_NONBLOCK
+        } else {
+            fl & !libc::O_NONBLOCK
+        };
+        check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, fl) }).map(drop)
+    }
+
+    fn cloexec(fd: BorrowedFd<'_>) -> SysResult<bool> {
+        let fl = check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) })?;
+        Ok(fl & libc::FD_CLOEXEC != 0)
+    }
+
+    fn epoll_writable(fd: BorrowedFd<'_>) -> SysResult<OwnedFd> {
+        let epfd = check(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) }).map(owned)?;
+        let mut ev = libc::epoll_event {
+            events: (libc::EPOLLOUT | libc::EPOLLET) as u32,
+            u64: 0,
+        };
+        check(unsafe {
+            libc::epoll_ctl(
+                epfd.as_raw_fd(),
+                libc::EPOLL_CTL_ADD,
+                fd.as_raw_fd(),
+                &mut ev,
+            )
+        })?;
+        Ok(epfd)
+    }
+
+    fn epoll_wait(epfd: BorrowedFd<'_>, timeout: i32) -> SysResult<usize> {
+        let mut ev = libc::epoll_event { events: 0, u64: 0 };
+        let n = check(unsafe { libc::epoll_wait(epfd.as_raw_fd(), &mut ev, 1, timeout) })?;
+        Ok(n as usize)
+    }
+}
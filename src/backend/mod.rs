@@ -0,0 +1,105 @@
+//! The syscalls underneath EventFD, behind a trait so the crate's public
+//! API doesn't depend on any one binding crate. The backend is picked with
+//! the `backend-nix` (default), `backend-rustix` and `backend-libc`
+//! features; if several are enabled, libc wins over rustix over nix.
+
+use crate::Flags;
+
+use std::os::unix::io::{BorrowedFd, OwnedFd, RawFd};
+use std::result;
+
+#[cfg(feature = "backend-libc")]
+mod libc;
+#[cfg(all(
+    feature = "backend-nix",
+    not(any(feature = "backend-rustix", feature = "backend-libc"))
+))]
+mod nix;
+#[cfg(all(feature = "backend-rustix", not(feature = "backend-libc")))]
+mod rustix;
+
+#[cfg(not(any(
+    feature = "backend-nix",
+    feature = "backend-rustix",
+    feature = "backend-libc"
+)))]
+compile_error!("enable one of the backend-nix, backend-rustix or backend-libc features");
+
+/// The backend in use.
+#[cfg(feature = "backend-libc")]
+pub(crate) type Sys = self::libc::Libc;
+#[cfg(all(
+    feature = "backend-nix",
+    not(any(feature = "backend-rustix", feature = "backend-libc"))
+))]
+pub(crate) type Sys = self::nix::Nix;
+#[cfg(all(feature = "backend-rustix", not(feature = "backend-libc")))]
+pub(crate) type Sys = self::rustix::Rustix;
+
+/// Result of a raw syscall: the errno on failure, to be mapped to an
+/// `Error` by the caller, which knows what e.g. `EAGAIN` means for it.
+pub(crate) type SysResult<T> = result::Result<T, i32>;
+
+pub(crate) const POLLIN: i16 = ::libc::POLLIN;
+pub(crate) const POLLOUT: i16 = ::libc::POLLOUT;
+
+/// One entry for `Backend::poll`, laid out like `struct pollfd`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PollFd {
+    pub fd: RawFd,
+    pub events: i16,
+    pub revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: RawFd, events: i16) -> PollFd {
+        PollFd {
+            fd,
+            events,
+            revents: 0,
+        }
+    }
+
+    /// Whether poll() reported anything for this fd.
+    pub fn is_ready(&self) -> bool {
+        self.revents != 0
+    }
+}
+
+pub(crate) trait Backend {
+    /// eventfd2(2).
+    fn eventfd(initval: u32, flags: Flags) -> SysResult<OwnedFd>;
+
+    fn read(fd: BorrowedFd<'_>, buf: &mut [u8]) -> SysResult<usize>;
+
+    fn write(fd: BorrowedFd<'_>, buf: &[u8]) -> SysResult<usize>;
+
+    /// Duplicate `fd`, atomically setting close-on-exec if asked to.
+    fn dup(fd: BorrowedFd<'_>, cloexec: bool) -> SysResult<OwnedFd>;
+
+    /// close(2), for callers that want to see its error rather than have
+    /// `OwnedFd` drop it.
+    fn close(fd: OwnedFd) -> SysResult<()>;
+
+    /// poll(2) with a timeout in milliseconds, -1 meaning forever.
+    /// Returns the number of entries with non-zero `revents`.
+    fn poll(fds: &mut [PollFd], timeout: i32) -> SysResult<usize>;
+
+    /// Whether `O_NONBLOCK` is set on the file description.
+    fn nonblocking(fd: BorrowedFd<'_>) -> SysResult<bool>;
+
+    fn set_nonblocking(fd: BorrowedFd<'_>, nonblocking: bool) -> SysResult<()>;
+
+    /// Whether `FD_CLOEXEC` is set on the fd.
+    fn cloexec(fd: BorrowedFd<'_>) -> SysResult<bool>;
+
+    /// A close-on-exec epoll instance watching `fd` for `EPOLLOUT`,
+    /// edge-triggered. An eventfd signals that edge on every read, so this
+    /// reports each time room is made, where poll() would keep reporting
+    /// as long as there is room for 1.
+    fn epoll_writable(fd: BorrowedFd<'_>) -> SysResult<OwnedFd>;
+
+    /// epoll_wait(2) for one event with a timeout in milliseconds, -1
+    /// meaning forever. Returns 0 on timeout.
+    fn epoll_wait(epfd: BorrowedFd<'_>, timeout: i32) -> SysResult<usize>;
+}
@@ -0,0 +1,100 @@
+use super::{Backend, PollFd, SysResult};
+use crate::Flags;
+
+use ::nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use ::nix::poll::{self, PollFlags};
+use ::nix::sys::epoll::{self, EpollCreateFlags, EpollEvent, EpollFlags, EpollOp};
+use ::nix::sys::eventfd::{eventfd, EfdFlags};
+use ::nix::unistd;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+/// Syscalls through the nix crate.
+pub(crate) enum Nix {}
+
+fn errno(err: ::nix::Error) -> i32 {
+    err.as_errno().map_or(libc::EIO, |e| e as i32)
+}
+
+/// Take ownership of an fd the kernel just returned.
+fn owned(fd: RawFd) -> OwnedFd {
+    // Nothing else has seen this fd yet.
+    unsafe { OwnedFd::from_raw_fd(fd) }
+}
+
+impl Backend for Nix {
+    fn eventfd(initval: u32, flags: Flags) -> SysResult<OwnedFd> {
+        let flags = EfdFlags::from_bits_truncate(flags.bits());
+        eventfd(initval, flags).map(owned).map_err(errno)
+    }
+
+    fn read(fd: BorrowedFd<'_>, buf: &mut [u8]) -> SysResult<usize> {
+        unistd::read(fd.as_raw_fd(), buf).map_err(errno)
+    }
+
+    fn write(fd: BorrowedFd<'_>, buf: &[u8]) -> SysResult<usize> {
+        unistd::write(fd.as_raw_fd(), buf).map_err(errno)
+    }
+
+    fn dup(fd: BorrowedFd<'_>, cloexec: bool) -> SysResult<OwnedFd> {
+        let fd = if cloexec {
+            fcntl(fd.as_raw_fd(), FcntlArg::F_DUPFD_CLOEXEC(0))
+        } else {
+            unistd::dup(fd.as_raw_fd())
+        };
+        fd.map(owned).map_err(errno)
+    }
+
+    fn close(fd: OwnedFd) -> SysResult<()> {
+        unistd::close(fd.into_raw_fd()).map_err(errno)
+    }
+
+    fn poll(fds: &mut [PollFd], timeout: i32) -> SysResult<usize> {
+        let mut pfds: Vec<poll::PollFd> = fds
+            .iter()
+            .map(|p| poll::PollFd::new(p.fd, PollFlags::from_bits_truncate(p.events)))
+            .collect();
+        let n = poll::poll(&mut pfds, timeout).map_err(errno)?;
+        for (p, pfd) in fds.iter_mut().zip(&pfds) {
+            p.revents = pfd.revents().map_or(0, |r| r.bits());
+        }
+        Ok(n as usize)
+    }
+
+    fn nonblocking(fd: BorrowedFd<'_>) -> SysResult<bool> {
+        let fl = fcntl(fd.as_raw_fd(), FcntlArg::F_GETFL).map_err(errno)?;
+        Ok(OFlag::from_bits_truncate(fl).contains(OFlag::O_NONBLOCK))
+    }
+
+    fn set_nonblocking(fd: BorrowedFd<'_>, nonblocking: bool) -> SysResult<()> {
+        let fl = fcntl(fd.as_raw_fd(), FcntlArg::F_GETFL).map_err(errno)?;
+        let mut fl = OFlag::from_bits_truncate(fl);
+        fl.set(OFlag::O_NONBLOCK, nonblocking);
+        fcntl(fd.as_raw_fd(), FcntlArg::F_SETFL(fl)).map_err(errno)?;
+        Ok(())
+    }
+
+    fn cloexec(fd: BorrowedFd<'_>) -> SysResult<bool> {
+        let fl = fcntl(fd.as_raw_fd(), FcntlArg::F_GETFD).map_err(errno)?;
+        Ok(FdFlag::from_bits_truncate(fl).contains(FdFlag::FD_CLOEXEC))
+    }
+
+    fn epoll_writable(fd: BorrowedFd<'_>) -> SysResult<OwnedFd> {
+        let epfd = epoll::epoll_create1(EpollCreateFlags::EPOLL_CLOEXEC)
+            .map(owned)
+            .map_err(errno)?;
+        let mut ev = EpollEvent::new(EpollFlags::EPOLLOUT | EpollFlags::EPOLLET, 0);
+        epoll::epoll_ctl(
+            epfd.as_raw_fd(),
+            EpollOp::EpollCtlAdd,
+            fd.as_raw_fd(),
+            &mut ev,
+        )
+        .map_err(errno)?;
+        Ok(epfd)
+    }
+
+    fn epoll_wait(epfd: BorrowedFd<'_>, timeout: i32) -> SysResult<usize> {
+        let mut events = [EpollEvent::empty()];
+        epoll::epoll_wait(epfd.as_raw_fd(), &mut events, timeout as isize).map_err(errno)
+    }
+}
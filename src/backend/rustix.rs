@@ -0,0 +1,111 @@
+use super::{Backend, PollFd, SysResult};
+use crate::Flags;
+
+use ::rustix::event::{self, epoll, EventfdFlags, PollFlags, Timespec};
+use ::rustix::fs::{self, OFlags};
+use ::rustix::io::{self, Errno, FdFlags};
+use std::os::unix::io::{BorrowedFd, IntoRawFd, OwnedFd};
+
+/// Syscalls through the rustix crate.
+pub(crate) enum Rustix {}
+
+fn errno(err: Errno) -> i32 {
+    err.raw_os_error()
+}
+
+/// A timeout in milliseconds as rustix takes it, with `None` for -1.
+fn timespec(timeout: i32) -> Option<Timespec> {
+    if timeout < 0 {
+        return None;
+    }
+    Some(Timespec {
+        tv_sec: i64::from(timeout / 1000),
+        tv_nsec: i64::from(timeout % 1000) * 1_000_000,
+    })
+}
+
+impl Backend for Rustix {
+    fn eventfd(initval: u32, flags: Flags) -> SysResult<OwnedFd> {
+        let flags = EventfdFlags::from_bits_truncate(flags.bits() as u32);
+        event::eventfd(initval, flags).map_err(errno)
+    }
+
+    fn read(fd: BorrowedFd<'_>, buf: &mut [u8]) -> SysResult<usize> {
+        io::read(fd, buf).map_err(errno)
+    }
+
+    fn write(fd: BorrowedFd<'_>, buf: &[u8]) -> SysResult<usize> {
+        io::write(fd, buf).map_err(errno)
+    }
+
+    fn dup(fd: BorrowedFd<'_>, cloexec: bool) -> SysResult<OwnedFd> {
+        if cloexec {
+            io::fcntl_dupfd_cloexec(fd, 0).map_err(errno)
+        } else {
+            io::dup(fd).map_err(errno)
+        }
+    }
+
+    fn close(fd: OwnedFd) -> SysResult<()> {
+        // rustix doesn't report close errors, so go to libc for this one.
+        if unsafe { ::libc::close(fd.into_raw_fd()) } == -1 {
+            return Err(std::io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(::libc::EIO));
+        }
+        Ok(())
+    }
+
+    fn poll(fds: &mut [PollFd], timeout: i32) -> SysResult<usize> {
+        // The fds are kept open by our callers for the duration of the call.
+        let mut pfds: Vec<event::PollFd<'_>> = fds
+            .iter()
+            .map(|p| {
+                let fd = unsafe { BorrowedFd::borrow_raw(p.fd) };
+                let events = PollFlags::from_bits_truncate(p.events as u16);
+                event::PollFd::from_borrowed_fd(fd, events)
+            })
+            .collect();
+        let n = event::poll(&mut pfds, timespec(timeout).as_ref()).map_err(errno)?;
+        for (p, pfd) in fds.iter_mut().zip(&pfds) {
+            p.revents = pfd.revents().bits() as i16;
+        }
+        Ok(n)
+    }
+
+    fn nonblocking(fd: BorrowedFd<'_>) -> SysResult<bool> {
+        let fl = fs::fcntl_getfl(fd).map_err(errno)?;
+        Ok(fl.contains(OFlags::NONBLOCK))
+    }
+
+    fn set_nonblocking(fd: BorrowedFd<'_>, nonblocking: bool) -> SysResult<()> {
+        let mut fl = fs::fcntl_getfl(fd).map_err(errno)?;
+        fl.set(OFlags::NONBLOCK, nonblocking);
+        fs::fcntl_setfl(fd, fl).map_err(errno)
+    }
+
+    fn cloexec(fd: BorrowedFd<'_>) -> SysResult<bool> {
+        let fl = io::fcntl_getfd(fd).map_err(errno)?;
+        Ok(fl.contains(FdFlags::CLOEXEC))
+    }
+
+    fn epoll_writable(fd: BorrowedFd<'_>) -> SysResult<OwnedFd> {
+        let epfd = epoll::create(epoll::CreateFlags::CLOEXEC).map_err(errno)?;
+        epoll::add(
+            &epfd,
+            fd,
+            epoll::EventData::new_u64(0),
+            epoll::EventFlags::OUT | epoll::EventFlags::ET,
+        )
+        .map_err(errno)?;
+        Ok(epfd)
+    }
+
+    fn epoll_wait(epfd: BorrowedFd<'_>, timeout: i32) -> SysResult<usize> {
+        let mut events = [epoll::Event {
+            flags: epoll::EventFlags::empty(),
+            data: epoll::EventData::new_u64(0),
+        }];
+        epoll::wait(epfd, &mut events, timespec(timeout).as_ref()).map_err(errno)
+    }
+}
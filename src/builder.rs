@@ -0,0 +1,142 @@
+use crate::{Counter, EventFD, Flags, Mode, Result, Semaphore};
+
+use std::marker::PhantomData;
+
+/// Options for creating an EventFD, started with `EventFD::builder()`.
+///
+/// Unlike `EventFD::new`, the fd is close-on-exec unless asked otherwise.
+/// The mode is part of the builder's type, like it is for `EventFD`: it
+/// builds a counter unless `semaphore` is called.
+///
+/// ```
+/// use eventfd::{EventFD, Semaphore};
+///
+/// let counter: EventFD = EventFD::builder().initial(3).build().unwrap();
+/// let sema: EventFD<Semaphore> = EventFD::builder()
+///     .semaphore()
+///     .nonblocking(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder<M: Mode = Counter> {
+    initial: u32,
+    nonblocking: bool,
+    cloexec: bool,
+    interruptible: bool,
+    mode: PhantomData<M>,
+}
+
+impl Builder<Counter> {
+    /// A builder for a blocking, close-on-exec counter starting at 0.
+    /// Same as `EventFD::builder()`.
+    pub fn new() -> Builder {
+        Builder {
+            initial: 0,
+            nonblocking: false,
+            cloexec: true,
+            interruptible: false,
+            mode: PhantomData,
+        }
+    }
+
+    /// Build a semaphore-mode EventFD (`EFD_SEMAPHORE`) instead.
+    pub fn semaphore(self) -> Builder<Semaphore> {
+        Builder {
+            initial: self.initial,
+            nonblocking: self.nonblocking,
+            cloexec: self.cloexec,
+            interruptible: self.interruptible,
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<M: Mode> Builder<M> {
+    /// Initial value of the counter. Defaults to 0.
+    pub fn initial(mut self, initial: u32) -> Builder<M> {
+        self.initial = initial;
+        self
+    }
+
+    /// Whether to use `EFD_NONBLOCK`. Defaults to false.
+    pub fn nonblocking(mut self, nonblocking: bool) -> Builder<M> {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Whether to use `EFD_CLOEXEC`. Defaults to true, so the fd isn't
+    /// leaked into programs started with `exec`.
+    pub fn cloexec(mut self, cloexec: bool) -> Builder<M> {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Whether signals interrupt blocking calls with `Error::Interrupted`
+    /// rather than having them restarted. Defaults to false; see
+    /// `EventFD::set_interruptible`.
+    pub fn interruptible(mut self, interruptible: bool) -> Builder<M> {
+        self.interruptible = interruptible;
+        self
+    }
+
+    /// The flags `build` passes to eventfd().
+    fn flags(&self) -> Flags {
+        let mut flags = Flags::empty();
+        flags.set(Flags::EFD_SEMAPHORE, M::SEMAPHORE);
+        flags.set(Flags::EFD_NONBLOCK, self.nonblocking);
+        flags.set(Flags::EFD_CLOEXEC, self.cloexec);
+        flags
+    }
+
+    /// Create the EventFD.
+    pub fn build(&self) -> Result<EventFD<M>> {
+        let mut efd = EventFD::create(self.initial, self.flags())?;
+        efd.set_interruptible(self.interruptible);
+        Ok(efd)
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Builder;
+    use crate::backend::{Backend, Sys};
+    use crate::{Error, EventFD, Flags};
+    use std::os::unix::io::AsFd;
+
+    #[test]
+    fn test_flags() {
+        let b = Builder::new();
+        assert_eq!(b.flags(), Flags::EFD_CLOEXEC);
+        assert_eq!(
+            b.semaphore().cloexec(false).nonblocking(true).flags(),
+            Flags::EFD_SEMAPHORE | Flags::EFD_NONBLOCK
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let efd = EventFD::builder().initial(3).build().unwrap();
+        assert!(Sys::cloexec(efd.as_fd()).unwrap());
+        assert_eq!(efd.read().unwrap(), 3);
+        assert!(!efd.is_interruptible());
+
+        let efd = EventFD::builder().interruptible(true).build().unwrap();
+        assert!(efd.is_interruptible());
+
+        let sema = EventFD::builder()
+            .initial(1)
+            .semaphore()
+            .nonblocking(true)
+            .build()
+            .unwrap();
+        assert_eq!(sema.read().unwrap(), 1);
+        assert_eq!(sema.read(), Err(Error::WouldBlock));
+    }
+}
@@ -0,0 +1,155 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// Errors from eventfd operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A non-blocking read found the counter at zero.
+    WouldBlock,
+    /// A non-blocking write would have taken the counter past its maximum
+    /// of 0xfffffffffffffffe.
+    Overflow,
+    /// `u64::MAX` was written, which eventfd never accepts.
+    InvalidValue,
+    /// The call was interrupted by a signal (`EINTR`).
+    Interrupted,
+    /// A read transferred this many bytes instead of 8.
+    ShortRead(usize),
+    /// A write transferred this many bytes instead of 8.
+    ShortWrite(usize),
+    /// `drain_up_to` took the `max` asked for plus this much more, and
+    /// couldn't put the excess back because the counter had filled up in
+    /// the meantime. The caller now holds both.
+    Excess(u64),
+    /// Any other failure, with its errno.
+    Os(i32),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    /// Map the errno from a failed read(2) or poll(2).
+    pub(crate) fn from_read_errno(errno: i32) -> Error {
+        match errno {
+            libc::EAGAIN => Error::WouldBlock,
+            libc::EINTR => Error::Interrupted,
+            errno => Error::Os(errno),
+        }
+    }
+
+    /// Map the errno from a failed write(2), where `EAGAIN` means the
+    /// counter is full.
+    pub(crate) fn from_write_errno(errno: i32) -> Error {
+        match errno {
+            libc::EAGAIN => Error::Overflow,
+            libc::EINVAL => Error::InvalidValue,
+            errno => Error::from_read_errno(errno),
+        }
+    }
+
+    /// The closest `io::ErrorKind`. `Overflow` is reported as `WouldBlock`,
+    /// since both mean a non-blocking operation should be retried once the
+    /// fd is ready.
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::WouldBlock | Error::Overflow => io::ErrorKind::WouldBlock,
+            Error::InvalidValue => io::ErrorKind::InvalidInput,
+            Error::Interrupted => io::ErrorKind::Interrupted,
+            Error::ShortRead(_) => io::ErrorKind::UnexpectedEof,
+            Error::ShortWrite(_) => io::ErrorKind::WriteZero,
+            Error::Excess(_) => io::ErrorKind::Other,
+            Error::Os(errno) => io::Error::from_raw_os_error(errno).kind(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::WouldBlock => write!(f, "eventfd counter is zero"),
+            Error::Overflow => write!(f, "eventfd counter would overflow"),
+            Error::InvalidValue => write!(f, "cannot write u64::MAX to an eventfd"),
+            Error::Interrupted => write!(f, "interrupted by a signal"),
+            Error::ShortRead(n) => write!(f, "eventfd read returned {} bytes, expected 8", n),
+            Error::ShortWrite(n) => write!(f, "eventfd write took {} bytes, expected 8", n),
+            Error::Excess(n) => write!(
+                f,
+                "drained {} more than asked for and couldn't return it",
+                n
+            ),
+            Error::Os(errno) => io::Error::from_raw_os_error(errno).fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// `Os` errors become plain OS errors; everything else is wrapped so that
+/// `From<io::Error>` can recover the original variant.
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Os(errno) => io::Error::from_raw_os_error(errno),
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        if let Some(inner) = err.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            return *inner;
+        }
+        match err.raw_os_error() {
+            Some(errno) => Error::from_read_errno(errno),
+            None => match err.kind() {
+                io::ErrorKind::WouldBlock => Error::WouldBlock,
+                io::ErrorKind::Interrupted => Error::Interrupted,
+                _ => Error::Os(libc::EIO),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use crate::{EventFD, Flags};
+    use std::io;
+
+    #[test]
+    fn test_io_roundtrip() {
+        let errs = [
+            Error::WouldBlock,
+            Error::Overflow,
+            Error::InvalidValue,
+            Error::Interrupted,
+            Error::ShortRead(3),
+            Error::ShortWrite(5),
+            Error::Excess(7),
+            Error::Os(libc::EBADF),
+        ];
+        for &err in &errs {
+            let ioerr = io::Error::from(err);
+            assert_eq!(ioerr.kind(), err.kind());
+            assert_eq!(Error::from(ioerr), err);
+        }
+        assert_eq!(
+            io::Error::from(Error::Os(libc::EBADF)).raw_os_error(),
+            Some(libc::EBADF)
+        );
+    }
+
+    #[test]
+    fn test_write_errors() {
+        let efd = EventFD::new(0, Flags::EFD_NONBLOCK).unwrap();
+
+        assert_eq!(efd.write(u64::MAX), Err(Error::InvalidValue));
+        efd.write(u64::MAX - 1).unwrap();
+        assert_eq!(efd.write(1), Err(Error::Overflow));
+        assert_eq!(efd.read(), Ok(u64::MAX - 1));
+        assert_eq!(efd.read(), Err(Error::WouldBlock));
+    }
+}
@@ -0,0 +1,486 @@
+use crate::backend::{self, PollFd};
+use crate::{wait, EventFD, Result};
+
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::mem;
+use std::os::unix::io::{AsRawFd, BorrowedFd, RawFd};
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Instant;
+
+/// Run `fut` to completion on the current thread, sleeping on an eventfd
+/// while it is pending. See `LocalExecutor`.
+pub fn block_on<F: Future>(fut: F) -> Result<F::Output> {
+    LocalExecutor::new()?.block_on(fut)
+}
+
+/// A spawned future and the waker it is always polled with, so that
+/// wakers it handed out earlier stay valid.
+struct Task {
+    fut: Pin<Box<dyn Future<Output = ()>>>,
+    waker: TaskWaker,
+}
+
+/// An entry in the task list, indexed by task id.
+enum Slot {
+    Free,
+    /// Taken out to be polled. The id stays reserved, so a task spawned
+    /// during the poll can't be given it and then overwritten.
+    Running,
+    Task(Task),
+}
+
+/// Task id used for the future passed to `block_on`.
+const MAIN: usize = usize::MAX;
+
+/// A small single-threaded executor that parks in poll() on an eventfd.
+///
+/// Wakers write to that eventfd, so they can be used from any thread.
+/// Because the thread sleeps in poll() rather than `thread::park`, it can
+/// wait on other fds at the same time: awaiting `readable` wakes the task
+/// once the given fd becomes readable, without needing a separate reactor.
+pub struct LocalExecutor {
+    shared: Arc<Shared>,
+    reactor: Rc<Reactor>,
+    tasks: RefCell<Vec<Slot>>,
+}
+
+impl LocalExecutor {
+    /// Create an executor with its own non-blocking eventfd.
+    pub fn new() -> Result<LocalExecutor> {
+        let efd = EventFD::builder().nonblocking(true).build()?;
+        Ok(LocalExecutor {
+            shared: Arc::new(Shared {
+                efd,
+                ready: Mutex::new(Vec::new()),
+            }),
+            reactor: Rc::default(),
+            tasks: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Add a task to be run alongside the future given to `block_on`.
+    /// Tasks only make progress while `block_on` is running, and any still
+    /// pending when the executor is dropped are dropped with it.
+    pub fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let mut tasks = self.tasks.borrow_mut();
+        let id = match tasks.iter().position(|s| matches!(s, Slot::Free)) {
+            Some(id) => id,
+            None => {
+                tasks.push(Slot::Free);
+                tasks.len() - 1
+            }
+        };
+        tasks[id] = Slot::Task(Task {
+            fut: Box::pin(fut),
+            waker: self.shared.waker(id),
+        });
+        self.shared.schedule(id);
+    }
+
+    /// Run `fut` to completion, along with any spawned tasks, returning its
+    /// output. Fails only if waiting on the eventfd fails.
+    pub fn block_on<F: Future>(&self, fut: F) -> Result<F::Output> {
+        let mut fut = pin!(fut);
+        let main = self.shared.waker(MAIN);
+        self.shared.schedule(MAIN);
+
+        loop {
+            let mut ready = mem::take(&mut *self.shared.lock()).into_iter();
+            while let Some(id) = ready.next() {
+                if id == MAIN {
+                    if let Poll::Ready(v) = main.poll(fut.as_mut()) {
+                        // Leave the rest queued for the next block_on.
+                        self.shared.lock().extend(ready);
+                        return Ok(v);
+                    }
+                } else {
+                    self.run_task(id);
+                }
+            }
+            // Registered fds are checked on every pass, so tasks that keep
+            // waking themselves can't starve them, but the thread only
+            // sleeps once nothing is ready to run.
+            let idle = self.shared.lock().is_empty();
+            self.park(idle)?;
+        }
+    }
+
+    /// A future that resolves once `fd` is readable. Nothing is read from
+    /// it, and the borrow keeps the fd open for as long as the future
+    /// exists.
+    pub fn readable<'a>(&self, fd: BorrowedFd<'a>) -> Readable<'a> {
+        Readable {
+            reactor: self.reactor.clone(),
+            fd,
+            key: None,
+        }
+    }
+
+    fn run_task(&self, id: usize) {
+        // Take the task out while polling it, so it can spawn more.
+        let mut task = {
+            let mut tasks = self.tasks.borrow_mut();
+            match tasks.get_mut(id).map(|s| mem::replace(s, Slot::Running)) {
+                Some(Slot::Task(task)) => task,
+                // Woken after it finished.
+                Some(slot) => {
+                    tasks[id] = slot;
+                    return;
+                }
+                None => return,
+            }
+        };
+        let done = task.waker.poll(task.fut.as_mut()).is_ready();
+        self.tasks.borrow_mut()[id] = if done { Slot::Free } else { Slot::Task(task) };
+    }
+
+    /// Wake the tasks whose registered fds are readable. With `block`,
+    /// first sleep until one is or a waker fires.
+    fn park(&self, block: bool) -> Result<()> {
+        if !block && self.reactor.sources.borrow().is_empty() {
+            return Ok(());
+        }
+        let mut fds = vec![PollFd::new(self.shared.efd.as_raw_fd(), backend::POLLIN)];
+        fds.extend(
+            self.reactor
+                .sources
+                .borrow()
+                .iter()
+                .map(|s| PollFd::new(s.fd, backend::POLLIN)),
+        );
+        let deadline = if block { None } else { Some(Instant::now()) };
+        if wait::poll_until(&mut fds, deadline, false)? == 0 {
+            return Ok(());
+        }
+        if fds[0].is_ready() {
+            self.shared.efd.drain()?;
+        }
+
+        let fired: Vec<Waker> = {
+            let mut sources = self.reactor.sources.borrow_mut();
+            let mut fired = Vec::new();
+            let mut i = 0;
+            sources.retain(|s| {
+                i += 1;
+                if fds[i].is_ready() {
+                    fired.push(s.waker.clone());
+                    return false;
+                }
+                true
+            });
+            fired
+        };
+        for waker in fired {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+/// The part of the executor that wakers, possibly on other threads, touch.
+struct Shared {
+    efd: EventFD,
+    ready: Mutex<Vec<usize>>,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<usize>> {
+        self.ready.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn schedule(&self, id: usize) {
+        self.lock().push(id);
+        let _ = self.efd.try_write(1);
+    }
+
+    fn waker(self: &Arc<Self>, id: usize) -> TaskWaker {
+        let inner = Arc::new(WakeTask {
+            id,
+            queued: AtomicBool::new(false),
+            shared: self.clone(),
+        });
+        TaskWaker {
+            task: inner.clone(),
+            waker: Waker::from(inner),
+        }
+    }
+}
+
+/// A task's waker, along with direct access to its state.
+struct TaskWaker {
+    task: Arc<WakeTask>,
+    waker: Waker,
+}
+
+impl TaskWaker {
+    /// Poll `fut` with this waker. The task is marked as no longer queued
+    /// first, so a wake during the poll queues it again.
+    fn poll<F: Future + ?Sized>(&self, fut: Pin<&mut F>) -> Poll<F::Output> {
+        self.task.queued.store(false, Ordering::SeqCst);
+        fut.poll(&mut Context::from_waker(&self.waker))
+    }
+}
+
+/// Wakes one task by queueing its id and writing to the eventfd. `queued`
+/// stops a task that's woken repeatedly from being queued more than once.
+struct WakeTask {
+    id: usize,
+    queued: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl Wake for WakeTask {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::SeqCst) {
+            self.shared.schedule(self.id);
+        }
+    }
+}
+
+/// Fds that pending `Readable` futures are waiting on.
+#[derive(Default)]
+struct Reactor {
+    next: Cell<u64>,
+    sources: RefCell<Vec<Source>>,
+}
+
+/// Registered by a `Readable`, which removes it again before its borrow of
+/// `fd` ends.
+struct Source {
+    key: u64,
+    fd: RawFd,
+    waker: Waker,
+}
+
+/// Future returned by `LocalExecutor::readable`.
+#[must_use = "futures do nothing unless awaited"]
+pub struct Readable<'a> {
+    reactor: Rc<Reactor>,
+    fd: BorrowedFd<'a>,
+    key: Option<u64>,
+}
+
+impl Readable<'_> {
+    fn deregister(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.reactor.sources.borrow_mut().retain(|s| s.key != key);
+        }
+    }
+}
+
+impl Future for Readable<'_> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match wait::poll_fd(
+            self.fd.as_raw_fd(),
+            backend::POLLIN,
+            Some(Instant::now()),
+            false,
+        ) {
+            Ok(false) => (),
+            res => {
+                self.deregister();
+                return Poll::Ready(res.map(drop));
+            }
+        }
+
+        let mut sources = self.reactor.sources.borrow_mut();
+        if let Some(s) = self
+            .key
+            .and_then(|k| sources.iter_mut().find(|s| s.key == k))
+        {
+            s.waker.clone_from(cx.waker());
+            return Poll::Pending;
+        }
+        // Either new, or its registration fired without the fd staying
+        // readable; register again.
+        let key = self.reactor.next.get();
+        self.reactor.next.set(key + 1);
+        sources.push(Source {
+            key,
+            fd: self.fd.as_raw_fd(),
+            waker: cx.waker().clone(),
+        });
+        drop(sources);
+        self.key = Some(key);
+        Poll::Pending
+    }
+}
+
+impl Drop for Readable<'_> {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{block_on, LocalExecutor};
+    use crate::{EventFD, Flags};
+    use std::cell::Cell;
+    use std::future::{self, Future};
+    use std::os::unix::io::AsFd;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Pending once, waking itself straight away.
+    async fn yield_now() {
+        let mut yielded = false;
+        future::poll_fn(|cx| {
+            if yielded {
+                return Poll::Ready(());
+            }
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Resolves once another thread has stored a value and woken it.
+    #[derive(Default)]
+    struct Slot(Mutex<(Option<u32>, Option<Waker>)>);
+
+    impl Future for &Slot {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            let mut slot = self.0.lock().unwrap();
+            match slot.0 {
+                Some(v) => Poll::Ready(v),
+                None => {
+                    slot.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_on() {
+        assert_eq!(block_on(async { 42 }).unwrap(), 42);
+
+        let slot = Arc::new(Slot::default());
+        let s = slot.clone();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            let mut slot = s.0.lock().unwrap();
+            slot.0 = Some(7);
+            slot.1.take().unwrap().wake();
+        });
+        assert_eq!(block_on(async { (&*slot).await + 1 }).unwrap(), 8);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn test_spawn() {
+        let ex = LocalExecutor::new().unwrap();
+        let done = Rc::new(Cell::new(0));
+        for _ in 0..3 {
+            let done = done.clone();
+            ex.spawn(async move {
+                yield_now().await;
+                yield_now().await;
+                done.set(done.get() + 1);
+            });
+        }
+        let n = ex
+            .block_on(async {
+                while done.get() < 3 {
+                    yield_now().await;
+                }
+                done.get()
+            })
+            .unwrap();
+        assert_eq!(n, 3);
+    }
+
+    #[test]
+    fn test_nested_spawn() {
+        let ex = Rc::new(LocalExecutor::new().unwrap());
+        let ran = Rc::new(Cell::new(false));
+        let (inner_ex, inner_ran) = (ex.clone(), ran.clone());
+        ex.spawn(async move {
+            let ran = inner_ran.clone();
+            inner_ex.spawn(async move { ran.set(true) });
+            yield_now().await;
+        });
+        ex.block_on(async {
+            while !ran.get() {
+                yield_now().await;
+            }
+        })
+        .unwrap();
+        assert!(ran.get());
+    }
+
+    #[test]
+    fn test_readable() {
+        let ex = LocalExecutor::new().unwrap();
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let c = efd.try_clone().unwrap();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            c.write(5).unwrap();
+        });
+
+        let v = ex
+            .block_on(async {
+                ex.readable(efd.as_fd()).await.unwrap();
+                efd.read().unwrap()
+            })
+            .unwrap();
+        assert_eq!(v, 5);
+        t.join().unwrap();
+
+        // A dropped future doesn't leave its fd registered.
+        let pending = ex.readable(efd.as_fd());
+        drop(pending);
+        assert!(ex.reactor.sources.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_readable_while_busy() {
+        // The main future never lets the ready queue empty, yet the task
+        // waiting on the fd still gets woken.
+        let ex = Rc::new(LocalExecutor::new().unwrap());
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let c = efd.try_clone().unwrap();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            c.write(1).unwrap();
+        });
+
+        let done = Rc::new(Cell::new(false));
+        let (inner_ex, d, other) = (ex.clone(), done.clone(), efd.try_clone().unwrap());
+        ex.spawn(async move {
+            inner_ex.readable(other.as_fd()).await.unwrap();
+            d.set(true);
+        });
+        ex.block_on(async {
+            while !done.get() {
+                yield_now().await;
+            }
+        })
+        .unwrap();
+        assert_eq!(efd.read().unwrap(), 1);
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+//! Parsing of `/proc/self/fdinfo/<fd>`, the only way to look at an
+//! eventfd's counter without consuming it.
+
+use crate::{Error, Result};
+
+use std::fs;
+use std::os::unix::io::RawFd;
+
+/// What the kernel reports about an eventfd in `/proc/self/fdinfo`,
+/// returned by `EventFD::fdinfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdInfo {
+    count: u64,
+    id: Option<u64>,
+    semaphore: Option<bool>,
+}
+
+impl FdInfo {
+    /// Read the fdinfo of `fd`, failing with `Error::Os(EINVAL)` if it
+    /// isn't an eventfd.
+    pub(crate) fn of(fd: RawFd) -> Result<FdInfo> {
+        let text = fs::read_to_string(format!("/proc/self/fdinfo/{}", fd))?;
+        FdInfo::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<FdInfo> {
+        let field = |name: &str| {
+            text.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .map(str::trim)
+        };
+        let count = field("eventfd-count")
+            .and_then(|v| u64::from_str_radix(v, 16).ok())
+            .ok_or(Error::Os(libc::EINVAL))?;
+        Ok(FdInfo {
+            count,
+            id: field("eventfd-id").and_then(|v| v.parse().ok()),
+            semaphore: field("eventfd-semaphore").map(|v| v != "0"),
+        })
+    }
+
+    /// The counter value at the time the fdinfo was read.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The kernel's identifier for the eventfd, shared by every fd that
+    /// refers to it, even across processes. Only reported since Linux 5.2.
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    /// Whether the eventfd is in semaphore mode. Only reported since
+    /// Linux 6.2.
+    pub fn is_semaphore(&self) -> Option<bool> {
+        self.semaphore
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FdInfo;
+    use crate::Error;
+
+    #[test]
+    fn test_parse() {
+        let text = "pos:\t0\nflags:\t02004002\nmnt_id:\t15\nino:\t1057\n\
+                    eventfd-count:               1f\neventfd-id: 3\n\
+                    eventfd-semaphore: 1\n";
+        let info = FdInfo::parse(text).unwrap();
+        assert_eq!(info.count(), 0x1f);
+        assert_eq!(info.id(), Some(3));
+        assert_eq!(info.is_semaphore(), Some(true));
+
+        let old = FdInfo::parse("eventfd-count:                0\n").unwrap();
+        assert_eq!(old.count(), 0);
+        assert_eq!(old.id(), None);
+        assert_eq!(old.is_semaphore(), None);
+
+        assert_eq!(FdInfo::parse("pos:\t0\n"), Err(Error::Os(libc::EINVAL)));
+    }
+}
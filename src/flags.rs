@@ -0,0 +1,25 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// Flags for creating an eventfd; see eventfd(2). These have the same
+    /// values as the kernel's, so `bits()` can be passed straight to the
+    /// syscall and `from_bits` accepts what it would.
+    pub struct Flags: i32 {
+        /// Set close-on-exec on the new fd.
+        const EFD_CLOEXEC = libc::EFD_CLOEXEC;
+        /// Make reads and writes fail instead of blocking.
+        const EFD_NONBLOCK = libc::EFD_NONBLOCK;
+        /// Read one unit at a time. Implied by the `Semaphore` mode.
+        const EFD_SEMAPHORE = libc::EFD_SEMAPHORE;
+    }
+}
+
+/// The name these flags had when they were re-exported from nix.
+#[deprecated(note = "use eventfd::Flags")]
+pub type EfdFlags = Flags;
+
+impl From<Flags> for i32 {
+    fn from(flags: Flags) -> i32 {
+        flags.bits()
+    }
+}
@@ -4,136 +4,533 @@
 //! This crate implements a simple binding for Linux eventfd(). See
 //! eventfd(2) for specific details of behaviour.
 
-use nix::sys::eventfd::eventfd;
-pub use nix::sys::eventfd::EfdFlags;
-use nix::unistd::{close, dup, read, write};
-
-use std::error::Error;
-use std::io;
-use std::mem;
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::mpsc;
-use std::thread;
-
-pub struct EventFD {
-    fd: RawFd,
-    flags: EfdFlags,
-}
+use crate::backend::{Backend, Sys, SysResult};
+use crate::nonblock::Nonblock;
 
-unsafe impl Send for EventFD {}
-unsafe impl Sync for EventFD {}
-
-macro_rules! nix_to_ioerr (
-    ($expr:expr) => ({
-        match $expr {
-            Ok(val) => val,
-            Err(ref err) => return Err(
-                if let Some(errno) = err.as_errno() {
-                    io::Error::from_raw_os_error(errno as i32)
-                } else {
-                    io::Error::new(io::ErrorKind::Other, err.description())
-                }
-            )
-        }
-    })
-);
+use std::cmp;
+use std::marker::PhantomData;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+mod backend;
+mod builder;
+mod error;
+mod executor;
+mod fdinfo;
+mod flags;
+mod mode;
+mod nonblock;
+mod notifier;
+mod semaphore;
+mod value;
+mod wait;
+mod watcher;
+pub use crate::builder::Builder;
+pub use crate::error::{Error, Result};
+pub use crate::executor::{block_on, LocalExecutor, Readable};
+pub use crate::fdinfo::FdInfo;
+#[allow(deprecated)]
+pub use crate::flags::{EfdFlags, Flags};
+pub use crate::mode::{Counter, Mode, Semaphore};
+pub use crate::notifier::Notifier;
+pub use crate::semaphore::{EventSemaphore, Permit};
+pub use crate::wait::{wait_all, wait_any};
+pub use crate::watcher::Watcher;
 
-impl EventFD {
-    /// Create a new EventFD. Flags is the bitwise OR of EFD_* constants, or 0 for no flags.
+#[cfg(feature = "async-io")]
+mod async_io_fd;
+#[cfg(feature = "async-io")]
+pub use crate::async_io_fd::AsyncIoExt;
+#[cfg(feature = "mio")]
+mod mio_source;
+#[cfg(feature = "tokio")]
+mod tokio_fd;
+#[cfg(feature = "tokio")]
+pub use crate::tokio_fd::AsyncEventFD;
+
+/// An eventfd. The mode is part of the type: `EventFD<Counter>` (the
+/// default) reads whole batches, while `EventFD<Semaphore>` reads one unit
+/// at a time.
+pub struct EventFD<M: Mode = Counter> {
+    fd: OwnedFd,
+    flags: Flags,
+    mode: PhantomData<M>,
+    interruptible: bool,
+    nonblock: Arc<Nonblock>,
+}
+
+impl EventFD<Counter> {
+    /// Create a new counter-mode EventFD. Flags is the bitwise OR of EFD_* constants, or 0 for
+    /// no flags. `EFD_SEMAPHORE` is rejected; use `new_semaphore` instead.
     /// The underlying file descriptor is closed when the EventFD instance's lifetime ends.
     ///
-    /// TODO: work out how to integrate this FD into the wider world
-    /// of fds. There's currently no way to poll/select on the fd.
-    pub fn new(initval: u32, flags: EfdFlags) -> io::Result<EventFD> {
+    /// The fd can be polled through `AsFd`/`AsRawFd`, or registered with a
+    /// reactor using the `tokio`, `mio` or `async-io` features.
+    pub fn new(initval: u32, flags: Flags) -> Result<EventFD> {
+        if flags.contains(Flags::EFD_SEMAPHORE) {
+            return Err(Error::Os(libc::EINVAL));
+        }
+        EventFD::create(initval, flags)
+    }
+
+    /// Start building an EventFD of either mode, with close-on-exec on by
+    /// default. See `Builder`.
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+}
+
+impl EventFD<Semaphore> {
+    /// Create a new semaphore-mode EventFD. `EFD_SEMAPHORE` is added to
+    /// `flags`; otherwise this is the same as `new`.
+    pub fn new_semaphore(initval: u32, flags: Flags) -> Result<EventFD<Semaphore>> {
+        EventFD::create(initval, flags | Flags::EFD_SEMAPHORE)
+    }
+}
+
+impl<M: Mode> EventFD<M> {
+    pub(crate) fn create(initval: u32, flags: Flags) -> Result<EventFD<M>> {
         Ok(EventFD {
-            fd: nix_to_ioerr!(eventfd(initval, flags)),
-            flags: flags,
+            fd: Sys::eventfd(initval, flags).map_err(Error::from_read_errno)?,
+            flags,
+            mode: PhantomData,
+            interruptible: false,
+            nonblock: Arc::new(Nonblock::new()),
         })
     }
 
     /// Read the current value of the eventfd. This will block until
-    /// the value is non-zero. In semaphore mode this will only ever
-    /// decrement the count by 1 and return 1; otherwise it atomically
-    /// returns the current value and sets it to zero.
-    pub fn read(&self) -> io::Result<u64> {
-        let mut buf = [0u8; 8];
-        let _ = nix_to_ioerr!(read(self.fd, &mut buf));
-        let val = unsafe { mem::transmute(buf) };
-        Ok(val)
-    }
-
-    /// Add to the current value. Blocks if the value would wrap u64.
-    pub fn write(&self, val: u64) -> io::Result<()> {
-        let buf: [u8; 8] = unsafe { mem::transmute(val) };
-        nix_to_ioerr!(write(self.fd, &buf));
-        Ok(())
+    /// the value is non-zero. For `EventFD<Semaphore>` this will only
+    /// ever decrement the count by 1 and return 1; for `EventFD<Counter>`
+    /// it atomically returns the current value and sets it to zero.
+    pub fn read(&self) -> Result<u64> {
+        loop {
+            let seq = self.nonblock.seq();
+            match self.read_once() {
+                Err(Error::WouldBlock) if self.nonblock.toggled_since(seq) => {
+                    // A clone's try_read may have had O_NONBLOCK set for
+                    // the moment; wait the way a blocking read would have.
+                    self.wait_for(backend::POLLIN)?;
+                }
+                res => return res,
+            }
+        }
     }
 
-    /// Return a stream of events.
-    ///
-    /// The channel has a synchronous sender because there's no point in building up a queue of
-    /// events; if this task blocks on send, the event state will still update.
-    ///
-    /// The task will exit if the receiver end is shut down.
-    ///
-    /// This will be a CPU-spin loop if the EventFD is created non-blocking.
+    /// A single read(2), reporting `EAGAIN` as `Error::WouldBlock`
+    /// whatever the EventFD's blocking mode.
+    fn read_once(&self) -> Result<u64> {
+        let mut buf = [0u8; value::SIZE];
+        let n = self
+            .restart(|| Sys::read(self.as_fd(), &mut buf))
+            .map_err(Error::from_read_errno)?;
+        value::decode(buf, n)
+    }
+
+    /// Like `read`, but give up if the eventfd hasn't become readable within
+    /// `timeout`, returning `Ok(None)`. Works whether or not the EventFD was
+    /// created with `EFD_NONBLOCK`.
+    pub fn read_timeout(&self, timeout: Duration) -> Result<Option<u64>> {
+        self.read_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Like `read_timeout`, but wait until an absolute deadline.
+    pub fn read_deadline(&self, deadline: Instant) -> Result<Option<u64>> {
+        self.read_until(Some(deadline))
+    }
+
+    fn read_until(&self, deadline: Option<Instant>) -> Result<Option<u64>> {
+        loop {
+            if !wait::poll_fd(
+                self.as_raw_fd(),
+                backend::POLLIN,
+                deadline,
+                self.interruptible,
+            )? {
+                return Ok(None);
+            }
+            // Another clone may have consumed the value since poll()
+            // returned; if so, go back to waiting for what's left of the time.
+            if let Some(v) = self.try_read()? {
+                return Ok(Some(v));
+            }
+        }
+    }
+
+    /// Whether a `read` would return straight away, i.e. the counter is
+    /// non-zero. Nothing is consumed.
+    pub fn is_readable(&self) -> Result<bool> {
+        self.poll_now(backend::POLLIN)
+    }
+
+    /// Whether a `write` of 1 would return straight away, i.e. the counter
+    /// is below its maximum.
+    pub fn is_writable(&self) -> Result<bool> {
+        self.poll_now(backend::POLLOUT)
+    }
+
+    fn poll_now(&self, events: i16) -> Result<bool> {
+        wait::poll_fd(self.as_raw_fd(), events, Some(Instant::now()), false)
+    }
+
+    /// Block until the counter is non-zero, without consuming it, so the
+    /// value can be left for someone else to `read`. Returns false if
+    /// `timeout` passed first; `None` waits forever.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+        wait::poll_fd(
+            self.as_raw_fd(),
+            backend::POLLIN,
+            deadline,
+            self.interruptible,
+        )
+    }
+
+    /// Read the current value if it is non-zero, or return `Ok(None)`
+    /// straight away. This never blocks, even if the EventFD wasn't
+    /// created with `EFD_NONBLOCK`.
     ///
-    /// XXX FIXME This has no way of terminating except if the other end closes the connection, and
-    /// only then if we're not blocked in the read()...
-    pub fn events(&self) -> mpsc::Receiver<u64> {
-        let (tx, rx) = mpsc::sync_channel(1);
-        let c = self.clone();
-
-        thread::spawn(move || loop {
-            match c.read() {
-                Ok(v) => match tx.send(v) {
-                    Ok(_) => (),
-                    Err(_) => break,
-                },
-                Err(e) => panic!("read failed: {}", e),
+    /// On a blocking EventFD, `O_NONBLOCK` is set for the duration of the
+    /// call. Clones coordinate this, so blocking calls on a clone still
+    /// block and concurrent `try_read`s don't clear the flag under each
+    /// other. Handles to the same eventfd that weren't cloned from this
+    /// one, such as fds passed to another process, aren't covered.
+    pub fn try_read(&self) -> Result<Option<u64>> {
+        match self.with_nonblocking(EventFD::read_once) {
+            Ok(v) => Ok(Some(v)),
+            Err(Error::WouldBlock) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Add to the current value if that won't overflow the counter,
+    /// returning whether it was written. Like `try_read`, this never
+    /// blocks.
+    pub fn try_write(&self, val: u64) -> Result<bool> {
+        match self.with_nonblocking(|efd| efd.write_once(val)) {
+            Ok(()) => Ok(true),
+            Err(Error::Overflow) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Take everything pending without blocking, returning the total, which
+    /// is 0 if there was nothing to take. A counter-mode EventFD is emptied
+    /// with a single read. In semaphore mode each read only takes 1, so
+    /// this reads until the count is exhausted.
+    pub fn drain(&self) -> Result<u64> {
+        self.drain_up_to(u64::MAX)
+    }
+
+    /// Like `drain`, but take at most `max`. In counter mode the whole
+    /// value is read and anything over `max` is written back, so other
+    /// readers can briefly see the counter at zero. The result never
+    /// exceeds `max`: if the excess can't be written back without
+    /// blocking, because writers have filled the counter in the meantime,
+    /// this fails with `Error::Excess` carrying what wasn't returned.
+    pub fn drain_up_to(&self, max: u64) -> Result<u64> {
+        if max == 0 {
+            return Ok(0);
+        }
+        if !M::SEMAPHORE {
+            let v = self.try_read()?.unwrap_or(0);
+            if v > max {
+                if !self.try_write(v - max)? {
+                    return Err(Error::Excess(v - max));
+                }
+                return Ok(max);
             }
-        });
+            return Ok(v);
+        }
+        self.with_nonblocking(|efd| {
+            let mut total = 0;
+            while total < max {
+                match efd.read_once() {
+                    Ok(v) => total += v,
+                    Err(Error::WouldBlock) => break,
+                    // Don't lose what was already taken; the error will
+                    // come up again on the next call.
+                    Err(_) if total > 0 => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(total)
+        })
+    }
 
-        rx
+    /// Add to the current value. Blocks if the value would wrap u64, or
+    /// fails with `Error::Overflow` if the EventFD is non-blocking.
+    /// `u64::MAX` can never be written and gives `Error::InvalidValue`.
+    pub fn write(&self, val: u64) -> Result<()> {
+        loop {
+            let seq = self.nonblock.seq();
+            match self.write_once(val) {
+                Err(Error::Overflow) if self.nonblock.toggled_since(seq) => {
+                    self.wait_for(backend::POLLOUT)?;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// A single write(2), reporting `EAGAIN` as `Error::Overflow` whatever
+    /// the EventFD's blocking mode.
+    fn write_once(&self, val: u64) -> Result<()> {
+        let buf = value::encode(val)?;
+        let n = self
+            .restart(|| Sys::write(self.as_fd(), &buf))
+            .map_err(Error::from_write_errno)?;
+        value::check_written(n)
+    }
+
+    /// Like `write`, but give up if the value can't be added within
+    /// `timeout`, returning whether it was written. Works whether or not
+    /// the EventFD was created with `EFD_NONBLOCK`.
+    pub fn write_timeout(&self, val: u64, timeout: Duration) -> Result<bool> {
+        self.write_until(val, Instant::now().checked_add(timeout))
+    }
+
+    fn write_until(&self, val: u64, deadline: Option<Instant>) -> Result<bool> {
+        if self.try_write(val)? {
+            return Ok(true);
+        }
+        // poll() reports the fd writable as soon as 1 more fits, which may
+        // be less than `val`. Room is only ever made by a read, so retry
+        // after each one; the edge-triggered epoll reports them all,
+        // including any that land between a failed write and the wait.
+        let epfd = Sys::epoll_writable(self.as_fd()).map_err(Error::from_read_errno)?;
+        loop {
+            if self.try_write(val)? {
+                return Ok(true);
+            }
+            match Sys::epoll_wait(epfd.as_fd(), wait::poll_timeout(deadline)) {
+                Ok(0) => return Ok(false),
+                Ok(_) => (),
+                Err(libc::EINTR) if !self.interruptible => (),
+                Err(e) => return Err(Error::from_read_errno(e)),
+            }
+        }
+    }
+
+    /// Add as much of `val` as fits without blocking, returning the amount
+    /// actually added. This is 0 when the counter is already at its
+    /// maximum of 0xfffffffffffffffe.
+    pub fn write_saturating(&self, val: u64) -> Result<u64> {
+        // Usually it all fits; only go to /proc when it doesn't.
+        let mut n = cmp::min(val, value::MAX);
+        while n != 0 && !self.try_write(n)? {
+            // Another writer may get in before the retry, so recheck the
+            // room left each time round.
+            n = cmp::min(n, self.headroom()?);
+        }
+        Ok(n)
+    }
+
+    /// How much can still be added before writes block, read from
+    /// `/proc/self/fdinfo`. Other handles to the eventfd may change the
+    /// counter at any time, so this is only a snapshot.
+    pub fn headroom(&self) -> Result<u64> {
+        Ok(value::MAX - self.peek()?)
+    }
+
+    /// The current value, without resetting it or waking anyone. Like
+    /// `headroom`, this is read from `/proc/self/fdinfo` and may be stale
+    /// by the time it returns; it's meant for debugging and metrics.
+    pub fn peek(&self) -> Result<u64> {
+        Ok(self.fdinfo()?.count())
+    }
+
+    /// Everything the kernel reports about this eventfd in
+    /// `/proc/self/fdinfo`.
+    pub fn fdinfo(&self) -> Result<FdInfo> {
+        FdInfo::of(self.as_raw_fd())
+    }
+
+    /// Set or clear `O_NONBLOCK` on the underlying file description.
+    /// The flag is shared with every clone of this EventFD.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        self.nonblock.set(self.as_fd(), nonblocking)?;
+        self.flags.set(Flags::EFD_NONBLOCK, nonblocking);
+        Ok(())
+    }
+
+    /// Whether the file description is in non-blocking mode, however
+    /// that was set. A clone's `try_read` or `try_write` in progress
+    /// doesn't count.
+    pub fn is_nonblocking(&self) -> Result<bool> {
+        self.nonblock.is_nonblocking(self.as_fd())
+    }
+
+    /// Choose whether a signal arriving while this EventFD is blocked in
+    /// `read`, `write` or one of the timed waits makes the call fail with
+    /// `Error::Interrupted`, so that signals can be used for cancellation.
+    /// By default such calls are restarted and the signal is invisible.
+    /// Clones made afterwards inherit the setting.
+    pub fn set_interruptible(&mut self, interruptible: bool) {
+        self.interruptible = interruptible;
+    }
+
+    /// Whether `EINTR` is reported instead of restarted; see
+    /// `set_interruptible`.
+    pub fn is_interruptible(&self) -> bool {
+        self.interruptible
+    }
+
+    /// Retry `op` while it fails with `EINTR`, unless this EventFD is
+    /// interruptible.
+    fn restart<T, F>(&self, mut op: F) -> SysResult<T>
+    where
+        F: FnMut() -> SysResult<T>,
+    {
+        loop {
+            match op() {
+                Err(libc::EINTR) if !self.interruptible => continue,
+                res => return res,
+            }
+        }
+    }
+
+    /// Run `op` with `O_NONBLOCK` set on the file description. Blocking
+    /// mode is put back once no clone is still in such a call.
+    fn with_nonblocking<T, F>(&self, op: F) -> Result<T>
+    where
+        F: FnOnce(&EventFD<M>) -> Result<T>,
+    {
+        self.nonblock.with_nonblocking(self.as_fd(), || op(self))
+    }
+
+    /// Block until `events` are signalled on the fd.
+    fn wait_for(&self, events: i16) -> Result<()> {
+        wait::poll_fd(self.as_raw_fd(), events, None, self.interruptible).map(drop)
+    }
+
+    /// Create a linked clone that shares the counter with this one,
+    /// returning an error rather than panicking if the fd can't be
+    /// duplicated. Like `File::try_clone`, the new fd has close-on-exec set.
+    pub fn try_clone(&self) -> Result<EventFD<M>> {
+        self.try_clone_with_cloexec(true)
+    }
+
+    /// Like `try_clone`, but choose whether the new fd is close-on-exec.
+    /// With `cloexec` the fd is duplicated atomically with
+    /// `F_DUPFD_CLOEXEC`, so it can't leak into a concurrent `exec`.
+    pub fn try_clone_with_cloexec(&self, cloexec: bool) -> Result<EventFD<M>> {
+        let fd = Sys::dup(self.as_fd(), cloexec).map_err(Error::from_read_errno)?;
+        let mut flags = self.flags;
+        flags.set(Flags::EFD_CLOEXEC, cloexec);
+        Ok(EventFD {
+            fd,
+            flags,
+            mode: PhantomData,
+            interruptible: self.interruptible,
+            nonblock: self.nonblock.clone(),
+        })
+    }
+
+    /// Close the fd, returning any error from close(2), which dropping the
+    /// EventFD silently ignores.
+    pub fn close(self) -> Result<()> {
+        Sys::close(self.fd).map_err(Error::from_read_errno)
+    }
+
+    /// Watch for events on a background thread.
+    ///
+    /// Each value read from the eventfd is sent on the returned channel,
+    /// followed by the error if a read fails. The channel has a synchronous
+    /// sender because there's no point in building up a queue of events; if
+    /// the thread blocks on send, the event state will still update.
+    ///
+    /// The thread exits when the `Watcher` is stopped or dropped, when the
+    /// receiver is dropped, or after delivering a read error.
+    pub fn watch(&self) -> Result<(Watcher, mpsc::Receiver<Result<u64>>)> {
+        Watcher::spawn(self)
     }
 }
 
-impl AsRawFd for EventFD {
+impl<M: Mode> AsRawFd for EventFD<M> {
     /// Return the raw underlying fd. The caller must make sure self's
-    /// lifetime is longer than any users of the fd.
+    /// lifetime is longer than any users of the fd; `as_fd` has the
+    /// borrow checker enforce that instead.
     fn as_raw_fd(&self) -> RawFd {
-        self.fd as RawFd
+        self.fd.as_raw_fd()
     }
 }
 
-impl Drop for EventFD {
-    fn drop(&mut self) {
-        let _ = close(self.fd);
+impl<M: Mode> AsFd for EventFD<M> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
     }
 }
 
-/// Construct a linked clone of an existing EventFD. Once created, the
-/// new instance interacts with the original in a way that's
-/// indistinguishable from the original.
-impl Clone for EventFD {
-    fn clone(&self) -> EventFD {
+impl<M: Mode> From<EventFD<M>> for OwnedFd {
+    fn from(efd: EventFD<M>) -> OwnedFd {
+        efd.fd
+    }
+}
+
+impl<M: Mode> IntoRawFd for EventFD<M> {
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+/// Adopt an existing eventfd, such as one inherited from a parent process
+/// or handed over by another library. The blocking state is read back from
+/// the fd.
+///
+/// # Safety
+///
+/// `fd` must be an open eventfd that nothing else will close, created with
+/// `EFD_SEMAPHORE` exactly when `M` is `Semaphore`.
+impl<M: Mode> FromRawFd for EventFD<M> {
+    unsafe fn from_raw_fd(fd: RawFd) -> EventFD<M> {
+        let fd = OwnedFd::from_raw_fd(fd);
+        let mut flags = Flags::empty();
+        flags.set(Flags::EFD_SEMAPHORE, M::SEMAPHORE);
+        if let Ok(nonblocking) = Sys::nonblocking(fd.as_fd()) {
+            flags.set(Flags::EFD_NONBLOCK, nonblocking);
+        }
+        if let Ok(cloexec) = Sys::cloexec(fd.as_fd()) {
+            flags.set(Flags::EFD_CLOEXEC, cloexec);
+        }
         EventFD {
-            fd: dup(self.fd).unwrap(),
-            flags: self.flags,
+            fd,
+            flags,
+            mode: PhantomData,
+            interruptible: false,
+            nonblock: Arc::new(Nonblock::new()),
         }
     }
 }
 
+/// Construct a linked clone of an existing EventFD. Once created, the
+/// new instance interacts with the original in a way that's
+/// indistinguishable from the original, including its mode.
+///
+/// Panics if the fd can't be duplicated, e.g. on `EMFILE`; use `try_clone`
+/// to handle that instead.
+impl<M: Mode> Clone for EventFD<M> {
+    fn clone(&self) -> EventFD<M> {
+        self.try_clone_with_cloexec(false)
+            .expect("failed to duplicate eventfd")
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{EfdFlags, EventFD};
-    use std::io;
+    use super::{Error, EventFD, Flags, Semaphore};
+    use crate::backend::{Backend, Sys};
+    use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Once};
     use std::thread;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn test_basic() {
         let (tx, rx) = std::sync::mpsc::channel();
-        let efd = match EventFD::new(10, EfdFlags::empty()) {
+        let efd = match EventFD::new(10, Flags::empty()) {
             Err(e) => panic!("new failed {}", e),
             Ok(fd) => fd,
         };
@@ -155,7 +552,7 @@ mod test {
 
     #[test]
     fn test_sema() {
-        let efd = match EventFD::new(0, EfdFlags::EFD_SEMAPHORE | EfdFlags::EFD_NONBLOCK) {
+        let efd = match EventFD::new_semaphore(0, Flags::EFD_NONBLOCK) {
             Err(e) => panic!("new failed {}", e),
             Ok(fd) => fd,
         };
@@ -166,8 +563,8 @@ mod test {
             //  becomes nonzero (at which time, the read(2) proceeds as
             //  described above) or fails with the error EAGAIN if the file
             //  descriptor has been made nonblocking.
-            // In Rust EAGAIN and EWOULDBLOCK are wrapped in io::ErrorKind::WouldBlock
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (), // ok
+            // EAGAIN from a read is reported as Error::WouldBlock
+            Err(Error::WouldBlock) => (), // ok
             Err(e) => panic!("unexpected error {}", e),
             Ok(v) => panic!("unexpected success {}", v),
         }
@@ -185,8 +582,8 @@ mod test {
             //  becomes nonzero (at which time, the read(2) proceeds as
             //  described above) or fails with the error EAGAIN if the file
             //  descriptor has been made nonblocking.
-            // In Rust EAGAIN and EWOULDBLOCK are wrapped in io::ErrorKind::WouldBlock
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (), // ok
+            // EAGAIN from a read is reported as Error::WouldBlock
+            Err(Error::WouldBlock) => (), // ok
             Err(e) => panic!("unexpected error {}", e),
             Ok(v) => panic!("unexpected success {}", v),
         }
@@ -194,25 +591,29 @@ mod test {
 
     #[test]
     fn test_stream() {
-        let efd = match EventFD::new(11, EfdFlags::EFD_SEMAPHORE) {
+        let efd = match EventFD::new_semaphore(11, Flags::empty()) {
             Err(e) => panic!("new failed {}", e),
             Ok(fd) => fd,
         };
+        let (watcher, rx) = efd.watch().unwrap();
         let mut count = 0;
 
-        // only take 10 of 11 so the stream task doesn't block in read and hang the test
-        for v in efd.events().iter().take(10) {
-            assert_eq!(v, 1);
-            count += v;
+        // only take 10 of 11; the watcher is stopped below without draining the rest
+        for v in rx.iter().take(10) {
+            assert_eq!(v.unwrap(), 1);
+            count += 1;
         }
 
-        assert_eq!(count, 10)
+        assert_eq!(count, 10);
+        drop(rx);
+        watcher.stop().unwrap();
+        assert!(watcher.join().is_ok());
     }
 
     #[test]
     fn test_chan() {
         let (tx, rx) = std::sync::mpsc::channel();
-        let efd = match EventFD::new(10, EfdFlags::empty()) {
+        let efd = match EventFD::new(10, Flags::empty()) {
             Err(e) => panic!("new failed {}", e),
             Ok(fd) => fd,
         };
@@ -231,4 +632,411 @@ mod test {
             Err(_) => panic!("failed"),
         }
     }
+
+    #[test]
+    fn test_mode_flags() {
+        match EventFD::new(0, Flags::EFD_SEMAPHORE) {
+            Err(Error::Os(libc::EINVAL)) => (), // ok
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("semaphore flag accepted for a counter"),
+        }
+
+        let sema = EventFD::new_semaphore(2, Flags::empty()).unwrap();
+        let clone: EventFD<Semaphore> = sema.clone();
+        assert_eq!(clone.read().unwrap(), 1);
+        assert_eq!(sema.read().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+
+        let start = Instant::now();
+        assert_eq!(efd.read_timeout(Duration::from_millis(20)).unwrap(), None);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        efd.write(4).unwrap();
+        assert_eq!(
+            efd.read_timeout(Duration::from_millis(20)).unwrap(),
+            Some(4)
+        );
+
+        let writer = efd.clone();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            writer.write(6).unwrap();
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert_eq!(efd.read_deadline(deadline).unwrap(), Some(6));
+        t.join().unwrap();
+
+        // A deadline in the past still picks up a pending value.
+        efd.write(1).unwrap();
+        assert_eq!(efd.read_deadline(Instant::now()).unwrap(), Some(1));
+        assert_eq!(efd.read_deadline(Instant::now()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_fd_ownership() {
+        let efd = EventFD::new(5, Flags::EFD_NONBLOCK).unwrap();
+        let raw = efd.into_raw_fd();
+        let efd: EventFD = unsafe { EventFD::from_raw_fd(raw) };
+        assert_eq!(efd.as_raw_fd(), raw);
+        assert_eq!(efd.read().unwrap(), 5);
+        // The adopted fd kept its non-blocking mode.
+        assert_eq!(efd.read(), Err(Error::WouldBlock));
+
+        efd.write(2).unwrap();
+        let owned = OwnedFd::from(efd);
+        let efd: EventFD = unsafe { EventFD::from_raw_fd(owned.into_raw_fd()) };
+        assert_eq!(efd.read().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_try_clone() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let cloexec = efd.try_clone().unwrap();
+        let inherit = efd.try_clone_with_cloexec(false).unwrap();
+
+        assert!(Sys::cloexec(cloexec.as_fd()).unwrap());
+        assert!(!Sys::cloexec(inherit.as_fd()).unwrap());
+
+        cloexec.write(3).unwrap();
+        inherit.write(4).unwrap();
+        assert_eq!(efd.read().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_try_read_write() {
+        // Blocking fd: try_read/try_write must still return immediately.
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        assert_eq!(efd.try_read().unwrap(), None);
+
+        assert!(efd.try_write(u64::MAX - 2).unwrap());
+        assert!(!efd.try_write(2).unwrap());
+        assert!(efd.try_write(1).unwrap());
+        assert_eq!(efd.try_read().unwrap(), Some(u64::MAX - 1));
+        assert_eq!(efd.try_read().unwrap(), None);
+        assert_eq!(efd.try_write(u64::MAX), Err(Error::InvalidValue));
+
+        // The fd is blocking again afterwards.
+        assert!(!Sys::nonblocking(efd.as_fd()).unwrap());
+
+        let sema = EventFD::new_semaphore(2, Flags::empty()).unwrap();
+        assert_eq!(sema.try_read().unwrap(), Some(1));
+        assert_eq!(sema.try_read().unwrap(), Some(1));
+        assert_eq!(sema.try_read().unwrap(), None);
+    }
+
+    #[test]
+    fn test_try_read_concurrent() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let c = efd.try_clone().unwrap();
+                thread::spawn(move || {
+                    for _ in 0..2000 {
+                        assert_eq!(c.try_read().unwrap(), None);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert!(!Sys::nonblocking(efd.as_fd()).unwrap());
+    }
+
+    #[test]
+    fn test_blocking_read_during_try() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (c, s) = (efd.try_clone().unwrap(), stop.clone());
+        let spinner = thread::spawn(move || {
+            while !s.load(Ordering::SeqCst) {
+                c.try_write(0).unwrap();
+            }
+        });
+
+        // A plain read on a clone keeps blocking while the other thread
+        // toggles O_NONBLOCK, rather than failing with WouldBlock.
+        let (reader, writer) = (efd.try_clone().unwrap(), efd.try_clone().unwrap());
+        let t = thread::spawn(move || reader.read());
+        thread::sleep(Duration::from_millis(20));
+        writer.write(9).unwrap();
+        assert_eq!(t.join().unwrap(), Ok(9));
+
+        // Likewise for a write blocked on a full counter.
+        efd.write(u64::MAX - 1).unwrap();
+        let t = thread::spawn(move || writer.write(1));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(efd.read().unwrap(), u64::MAX - 1);
+        assert_eq!(t.join().unwrap(), Ok(()));
+        assert_eq!(efd.read().unwrap(), 1);
+
+        stop.store(true, Ordering::SeqCst);
+        spinner.join().unwrap();
+    }
+
+    #[test]
+    fn test_nonblocking_set_elsewhere() {
+        // O_NONBLOCK set with fcntl(2), as a reactor adopting the fd would,
+        // is honoured rather than turned back into a blocking wait.
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        Sys::set_nonblocking(efd.as_fd(), true).unwrap();
+        assert!(efd.is_nonblocking().unwrap());
+        assert_eq!(efd.read(), Err(Error::WouldBlock));
+
+        efd.write(u64::MAX - 1).unwrap();
+        assert_eq!(efd.write(1), Err(Error::Overflow));
+
+        // A try_read in between leaves it that way.
+        assert_eq!(efd.try_read().unwrap(), Some(u64::MAX - 1));
+        assert!(Sys::nonblocking(efd.as_fd()).unwrap());
+        assert_eq!(efd.read(), Err(Error::WouldBlock));
+    }
+
+    #[test]
+    fn test_close() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let clone = efd.clone();
+        efd.close().unwrap();
+
+        clone.write(1).unwrap();
+        assert_eq!(clone.read().unwrap(), 1);
+    }
+
+    /// Install a no-op SIGUSR1 handler without `SA_RESTART`, so the signal
+    /// makes blocking syscalls fail with `EINTR`.
+    fn install_sigusr1() {
+        static INSTALL: Once = Once::new();
+        extern "C" fn noop(_: libc::c_int) {}
+        INSTALL.call_once(|| unsafe {
+            let mut sa: libc::sigaction = std::mem::zeroed();
+            sa.sa_sigaction = noop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut sa.sa_mask);
+            assert_eq!(libc::sigaction(libc::SIGUSR1, &sa, std::ptr::null_mut()), 0);
+        });
+    }
+
+    /// Run `f` on a new thread and pelt it with SIGUSR1 until it returns.
+    /// `release` is called once the thread has been signalled a few times.
+    fn signal_while<T, F, R>(f: F, release: R) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+        R: FnOnce(),
+    {
+        install_sigusr1();
+        let done = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let d = done.clone();
+        let t = thread::spawn(move || {
+            tx.send(unsafe { libc::pthread_self() }).unwrap();
+            let res = f();
+            d.store(true, Ordering::SeqCst);
+            res
+        });
+        let tid = rx.recv().unwrap();
+
+        let mut release = Some(release);
+        for i in 0.. {
+            if done.load(Ordering::SeqCst) {
+                break;
+            }
+            unsafe { libc::pthread_kill(tid, libc::SIGUSR1) };
+            thread::sleep(Duration::from_millis(5));
+            if i == 10 {
+                release.take().unwrap()();
+            }
+        }
+        t.join().unwrap()
+    }
+
+    #[test]
+    fn test_eintr_restart() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let c = efd.try_clone().unwrap();
+        assert!(!c.is_interruptible());
+        let v = signal_while(move || c.read(), || efd.write(7).unwrap());
+        assert_eq!(v, Ok(7));
+
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let res = signal_while(move || efd.read_timeout(Duration::from_millis(100)), || ());
+        assert_eq!(res, Ok(None));
+    }
+
+    #[test]
+    fn test_eintr_interruptible() {
+        let mut efd = EventFD::new(0, Flags::empty()).unwrap();
+        efd.set_interruptible(true);
+        let c = efd.try_clone().unwrap();
+        assert!(c.is_interruptible());
+        assert_eq!(
+            signal_while(move || c.read(), || ()),
+            Err(Error::Interrupted)
+        );
+
+        let c = efd.try_clone().unwrap();
+        let res = signal_while(move || c.read_timeout(Duration::from_secs(10)), || ());
+        assert_eq!(res, Err(Error::Interrupted));
+
+        // A full counter blocks writers too.
+        efd.write(u64::MAX - 1).unwrap();
+        let c = efd.try_clone().unwrap();
+        assert_eq!(
+            signal_while(move || c.write(1), || ()),
+            Err(Error::Interrupted)
+        );
+        assert_eq!(efd.read(), Ok(u64::MAX - 1));
+    }
+
+    #[test]
+    fn test_overflow_writes() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        assert_eq!(efd.headroom().unwrap(), u64::MAX - 1);
+
+        efd.write(u64::MAX - 11).unwrap();
+        assert_eq!(efd.headroom().unwrap(), 10);
+        assert_eq!(efd.write_saturating(4).unwrap(), 4);
+        assert_eq!(efd.write_saturating(100).unwrap(), 6);
+        assert_eq!(efd.write_saturating(1).unwrap(), 0);
+        assert_eq!(efd.headroom().unwrap(), 0);
+
+        let empty = EventFD::new(0, Flags::empty()).unwrap();
+        assert_eq!(empty.write_saturating(u64::MAX).unwrap(), u64::MAX - 1);
+
+        let start = Instant::now();
+        assert!(!efd.write_timeout(1, Duration::from_millis(20)).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(
+            efd.write_timeout(u64::MAX, Duration::from_millis(20)),
+            Err(Error::InvalidValue)
+        );
+
+        // Make room for 5 only once the writer is waiting for 8.
+        let c = efd.try_clone().unwrap();
+        let t = thread::spawn(move || c.write_timeout(8, Duration::from_secs(5)));
+        thread::sleep(Duration::from_millis(10));
+        let v = efd.read().unwrap();
+        efd.write(v - 3).unwrap();
+        thread::sleep(Duration::from_millis(10));
+        efd.write_saturating(100).unwrap();
+        efd.read().unwrap();
+        assert!(t.join().unwrap().unwrap());
+
+        // In semaphore mode each read frees just 1, and the writer has to
+        // notice every one of them.
+        let sema = EventFD::new_semaphore(0, Flags::empty()).unwrap();
+        sema.write(u64::MAX - 1).unwrap();
+        let c = sema.try_clone().unwrap();
+        let start = Instant::now();
+        let t = thread::spawn(move || c.write_timeout(3, Duration::from_secs(5)));
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(5));
+            sema.read().unwrap();
+        }
+        assert!(t.join().unwrap().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_peek() {
+        let efd = EventFD::new(5, Flags::empty()).unwrap();
+        assert_eq!(efd.peek().unwrap(), 5);
+        assert_eq!(efd.peek().unwrap(), 5);
+        efd.write(2).unwrap();
+        assert_eq!(efd.peek().unwrap(), 7);
+        assert_eq!(efd.read().unwrap(), 7);
+        assert_eq!(efd.peek().unwrap(), 0);
+
+        let info = efd.fdinfo().unwrap();
+        let clone = efd.try_clone().unwrap();
+        assert_eq!(clone.fdinfo().unwrap().id(), info.id());
+        let other = EventFD::new(0, Flags::empty()).unwrap();
+        if let Some(id) = info.id() {
+            assert_ne!(other.fdinfo().unwrap().id(), Some(id));
+        }
+
+        let sema = EventFD::new_semaphore(2, Flags::empty()).unwrap();
+        assert_eq!(sema.peek().unwrap(), 2);
+        assert_ne!(sema.fdinfo().unwrap().is_semaphore(), Some(false));
+        assert_ne!(info.is_semaphore(), Some(true));
+    }
+
+    #[test]
+    fn test_readiness() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        assert!(!efd.is_readable().unwrap());
+        assert!(efd.is_writable().unwrap());
+        assert!(!efd.wait_readable(Some(Duration::from_millis(10))).unwrap());
+
+        let c = efd.try_clone().unwrap();
+        let t = thread::spawn(move || c.wait_readable(None));
+        thread::sleep(Duration::from_millis(10));
+        efd.write(3).unwrap();
+        assert!(t.join().unwrap().unwrap());
+
+        // Waiting and checking leave the value in place.
+        assert!(efd.is_readable().unwrap());
+        assert!(efd.wait_readable(Some(Duration::ZERO)).unwrap());
+        assert_eq!(efd.read().unwrap(), 3);
+
+        efd.write(u64::MAX - 1).unwrap();
+        assert!(!efd.is_writable().unwrap());
+        efd.read().unwrap();
+        assert!(efd.is_writable().unwrap());
+    }
+
+    #[test]
+    fn test_drain() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        assert_eq!(efd.drain().unwrap(), 0);
+        efd.write(10).unwrap();
+        assert_eq!(efd.drain_up_to(4).unwrap(), 4);
+        assert_eq!(efd.peek().unwrap(), 6);
+        assert_eq!(efd.drain_up_to(0).unwrap(), 0);
+        assert_eq!(efd.drain().unwrap(), 6);
+        assert!(!efd.is_readable().unwrap());
+
+        let nb = EventFD::new(10, Flags::EFD_NONBLOCK).unwrap();
+        assert_eq!(nb.drain_up_to(4).unwrap(), 4);
+        assert_eq!(nb.drain().unwrap(), 6);
+
+        let sema = EventFD::new_semaphore(5, Flags::empty()).unwrap();
+        assert_eq!(sema.drain_up_to(2).unwrap(), 2);
+        assert_eq!(sema.drain().unwrap(), 3);
+        assert_eq!(sema.drain().unwrap(), 0);
+        // The blocking mode is restored afterwards.
+        assert!(!Sys::nonblocking(sema.as_fd()).unwrap());
+    }
+
+    #[test]
+    fn test_drain_excess() {
+        // A writer keeps refilling the counter, so sooner or later it's
+        // full again before drain_up_to can write the excess back.
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (c, s) = (efd.try_clone().unwrap(), stop.clone());
+        let filler = thread::spawn(move || {
+            while !s.load(Ordering::SeqCst) {
+                c.write_saturating(u64::MAX).unwrap();
+            }
+        });
+
+        let mut excess = None;
+        for _ in 0..100_000 {
+            match efd.drain_up_to(1) {
+                Ok(v) => assert!(v <= 1),
+                Err(Error::Excess(n)) => {
+                    excess = Some(n);
+                    break;
+                }
+                Err(e) => panic!("{}", e),
+            }
+        }
+        stop.store(true, Ordering::SeqCst);
+        filler.join().unwrap();
+        assert!(excess.unwrap() > 0);
+    }
 }
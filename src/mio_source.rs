@@ -0,0 +1,67 @@
+//! mio integration, enabled with the `mio` feature.
+
+use crate::{EventFD, Mode};
+
+use mio::event::Source;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// Register an EventFD with a `mio::Poll`.
+///
+/// mio's readiness is edge-triggered, so the EventFD should be created with
+/// `EFD_NONBLOCK` and read until it returns `WouldBlock` after each event.
+impl<M: Mode> Source for EventFD<M> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EventFD, Flags};
+    use mio::{Events, Interest, Poll, Token};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_mio_waker() {
+        let mut poll = Poll::new().unwrap();
+        let mut efd = EventFD::new(0, Flags::EFD_NONBLOCK).unwrap();
+        let waker = efd.clone();
+        poll.registry()
+            .register(&mut efd, Token(7), Interest::READABLE)
+            .unwrap();
+
+        let t = thread::spawn(move || waker.write(1).unwrap());
+
+        let mut events = Events::with_capacity(4);
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        let tokens: Vec<Token> = events.iter().map(|e| e.token()).collect();
+        assert_eq!(tokens, vec![Token(7)]);
+        assert_eq!(efd.read().unwrap(), 1);
+
+        t.join().unwrap();
+        poll.registry().deregister(&mut efd).unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+//! Type-level eventfd modes.
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The mode an EventFD was created in, which decides what `read` returns.
+/// Implemented only by `Counter` and `Semaphore`.
+pub trait Mode: private::Sealed + Send + Sync + Unpin + 'static {
+    /// Whether the fd is created with `EFD_SEMAPHORE`.
+    const SEMAPHORE: bool;
+}
+
+/// Counter mode: a read returns the whole accumulated count and resets it
+/// to zero.
+#[derive(Debug, Clone, Copy)]
+pub enum Counter {}
+
+/// Semaphore mode (`EFD_SEMAPHORE`): a read decrements the count by one
+/// and returns 1.
+#[derive(Debug, Clone, Copy)]
+pub enum Semaphore {}
+
+impl private::Sealed for Counter {}
+impl private::Sealed for Semaphore {}
+
+impl Mode for Counter {
+    const SEMAPHORE: bool = false;
+}
+
+impl Mode for Semaphore {
+    const SEMAPHORE: bool = true;
+}
@@ -0,0 +1,121 @@
+use crate::backend::{Backend, Sys};
+use crate::{Error, Result};
+
+use std::os::unix::io::BorrowedFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// `O_NONBLOCK` bookkeeping shared by an EventFD and all its clones.
+///
+/// The flag lives on the file description, so every clone sees it. Callers
+/// that need one non-blocking operation on a blocking EventFD go through
+/// `with_nonblocking`, which sets the flag for the first of them and clears
+/// it only when the last one is done, so overlapping callers can't restore
+/// blocking mode under each other.
+///
+/// Nothing else is assumed about the flag: it may also be changed behind
+/// our back, e.g. by a reactor that adopts the fd. A blocking call that
+/// gets `EAGAIN` therefore uses `toggled_since` to find out whether one of
+/// our own toggles could be the cause, rather than trusting a cached mode.
+#[derive(Debug)]
+pub(crate) struct Nonblock {
+    state: Mutex<State>,
+    /// Bumped each time `State::toggled` changes, so it's odd exactly
+    /// while the flag is set on behalf of `with_nonblocking`.
+    seq: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Operations currently running under `with_nonblocking`.
+    borrowed: usize,
+    /// Whether the flag was set for them and has to be cleared again when
+    /// the last one is done.
+    toggled: bool,
+}
+
+impl Nonblock {
+    pub fn new() -> Nonblock {
+        Nonblock {
+            state: Mutex::default(),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_toggled(&self, state: &mut State, toggled: bool) {
+        if state.toggled != toggled {
+            state.toggled = toggled;
+            self.seq.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// A token to take before a syscall and pass to `toggled_since` if it
+    /// fails with `EAGAIN`.
+    pub fn seq(&self) -> u64 {
+        self.seq.load(Ordering::Acquire)
+    }
+
+    /// Whether the flag may have been set by `with_nonblocking` at any
+    /// point since `seq` was taken.
+    pub fn toggled_since(&self, seq: u64) -> bool {
+        seq % 2 == 1 || self.seq() != seq
+    }
+
+    /// Whether the fd is in non-blocking mode, not counting a temporary
+    /// toggle by `with_nonblocking`.
+    pub fn is_nonblocking(&self, fd: BorrowedFd<'_>) -> Result<bool> {
+        let state = self.lock();
+        if state.toggled {
+            return Ok(false);
+        }
+        Sys::nonblocking(fd).map_err(Error::from_read_errno)
+    }
+
+    pub fn set(&self, fd: BorrowedFd<'_>, nonblocking: bool) -> Result<()> {
+        let mut state = self.lock();
+        if state.borrowed == 0 {
+            return Sys::set_nonblocking(fd, nonblocking).map_err(Error::from_read_errno);
+        }
+        // The flag has to stay set while borrowed; whether the last
+        // borrower clears it is the new setting.
+        self.set_toggled(&mut state, !nonblocking);
+        Ok(())
+    }
+
+    /// Run `op` with `O_NONBLOCK` set on `fd`, then put blocking mode back
+    /// once no other caller still needs the flag.
+    pub fn with_nonblocking<T, F>(&self, fd: BorrowedFd<'_>, op: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        {
+            let mut state = self.lock();
+            if state.borrowed == 0 && !Sys::nonblocking(fd).map_err(Error::from_read_errno)? {
+                // Bump seq before the flag goes on, so a blocking call that
+                // sees EAGAIN from it can't also see an unchanged seq.
+                self.set_toggled(&mut state, true);
+                if let Err(errno) = Sys::set_nonblocking(fd, true) {
+                    self.set_toggled(&mut state, false);
+                    return Err(Error::from_read_errno(errno));
+                }
+            }
+            state.borrowed += 1;
+        }
+
+        let res = op();
+
+        let mut state = self.lock();
+        state.borrowed -= 1;
+        if state.borrowed == 0 && state.toggled {
+            // Don't let a failed restore hide a value we've already
+            // consumed.
+            let _ = Sys::set_nonblocking(fd, false);
+            self.set_toggled(&mut state, false);
+        }
+        res
+    }
+}
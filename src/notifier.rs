@@ -0,0 +1,124 @@
+use crate::{Counter, EventFD, Mode, Result};
+
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::Arc;
+use std::task::{Wake, Waker};
+
+/// Wakes a task by adding 1 to an eventfd, bridging futures and
+/// fd-based event loops: a thread sleeping in poll() or epoll on the fd
+/// wakes whenever any future holding the waker fires.
+///
+/// Wrap it in an `Arc` and convert it with `Waker::from`, or use
+/// `EventFD::waker` for the common case.
+pub struct Notifier<M: Mode = Counter> {
+    efd: EventFD<M>,
+}
+
+impl<M: Mode> Notifier<M> {
+    /// Add 1 to the eventfd. This never blocks, whatever mode the fd or
+    /// its clones are in; a counter that is already full is left alone,
+    /// since whoever is waiting on it will wake anyway.
+    pub fn notify(&self) {
+        let _ = self.efd.try_write(1);
+    }
+
+    /// The eventfd being written to.
+    pub fn as_eventfd(&self) -> &EventFD<M> {
+        &self.efd
+    }
+
+    /// A `Waker` that notifies this eventfd.
+    pub fn into_waker(self) -> Waker {
+        Waker::from(Arc::new(self))
+    }
+}
+
+impl<M: Mode> From<EventFD<M>> for Notifier<M> {
+    fn from(efd: EventFD<M>) -> Notifier<M> {
+        Notifier { efd }
+    }
+}
+
+impl<M: Mode> Wake for Notifier<M> {
+    fn wake(self: Arc<Self>) {
+        self.notify()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notify()
+    }
+}
+
+impl<M: Mode> AsRawFd for Notifier<M> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.efd.as_raw_fd()
+    }
+}
+
+impl<M: Mode> AsFd for Notifier<M> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.efd.as_fd()
+    }
+}
+
+impl<M: Mode> EventFD<M> {
+    /// A `Waker` that adds 1 to this eventfd each time it is woken. It
+    /// holds its own clone of the fd, so it can outlive this EventFD.
+    pub fn waker(&self) -> Result<Waker> {
+        Ok(Notifier::from(self.try_clone()?).into_waker())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Notifier;
+    use crate::backend::{Backend, Sys};
+    use crate::{EventFD, Flags};
+    use std::os::unix::io::AsFd;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_waker() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let waker = efd.waker().unwrap();
+        waker.wake_by_ref();
+        let other = waker.clone();
+        other.wake();
+        assert_eq!(efd.read().unwrap(), 2);
+
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            waker.wake();
+        });
+        assert_eq!(efd.read().unwrap(), 1);
+        t.join().unwrap();
+
+        // Waking a full counter is a no-op rather than blocking.
+        let notifier = Notifier::from(efd.try_clone().unwrap());
+        efd.write(u64::MAX - 1).unwrap();
+        notifier.notify();
+        assert_eq!(efd.read().unwrap(), u64::MAX - 1);
+        assert!(!Sys::nonblocking(efd.as_fd()).unwrap());
+    }
+
+    #[test]
+    fn test_waker_any_mode() {
+        // Whatever mode a clone switches the fd to, a wake doesn't block.
+        let mut efd = EventFD::new(0, Flags::EFD_NONBLOCK).unwrap();
+        let waker = efd.waker().unwrap();
+        efd.set_nonblocking(false).unwrap();
+        efd.write(u64::MAX - 1).unwrap();
+        waker.wake_by_ref();
+        assert_eq!(efd.read().unwrap(), u64::MAX - 1);
+
+        waker.wake_by_ref();
+        assert_eq!(efd.read().unwrap(), 1);
+        assert!(!Sys::nonblocking(efd.as_fd()).unwrap());
+
+        efd.set_nonblocking(true).unwrap();
+        waker.wake();
+        assert_eq!(efd.read().unwrap(), 1);
+        assert!(Sys::nonblocking(efd.as_fd()).unwrap());
+    }
+}
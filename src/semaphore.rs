@@ -0,0 +1,356 @@
+use crate::{EventFD, Flags, Result, Semaphore};
+
+use std::collections::VecDeque;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// A counting semaphore built on an `EFD_SEMAPHORE` eventfd.
+///
+/// Clones share the same count, and because the count lives in the kernel
+/// the fd can also be handed to another process to limit work across
+/// processes.
+///
+/// The kernel only hands out one unit per read, so acquiring several is
+/// done here: acquirers queue up in FIFO order and only the one at the
+/// head takes units, which stops two large acquirers each holding part
+/// of what they need. The queue is shared by clones, but not with other
+/// processes or with handles made from the raw fd.
+#[derive(Clone)]
+pub struct EventSemaphore {
+    efd: EventFD<Semaphore>,
+    queue: Arc<Queue>,
+}
+
+impl EventSemaphore {
+    /// Create a semaphore with `permits` initially available.
+    pub fn new(permits: u32) -> Result<EventSemaphore> {
+        Ok(EventFD::new_semaphore(permits, Flags::empty())?.into())
+    }
+
+    /// Make `n` more permits available, waking up to `n` waiters.
+    pub fn release(&self, n: u64) -> Result<()> {
+        self.efd.write(n)
+    }
+
+    /// Take a permit, blocking until one is available. The permit is
+    /// released again when the returned guard is dropped.
+    pub fn acquire(&self) -> Result<Permit<'_>> {
+        self.acquire_many(1)
+    }
+
+    /// Take a permit if one is available right now.
+    pub fn try_acquire(&self) -> Result<Option<Permit<'_>>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Take a permit, giving up with `Ok(None)` if none becomes available
+    /// within `timeout`.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<Option<Permit<'_>>> {
+        self.acquire_many_timeout(1, timeout)
+    }
+
+    /// Take `n` permits at once, blocking until all of them are available.
+    /// They are all released together when the returned guard is dropped.
+    pub fn acquire_many(&self, n: u64) -> Result<Permit<'_>> {
+        let permit = self.acquire_until(n, None)?;
+        Ok(permit.expect("acquire without a deadline timed out"))
+    }
+
+    /// Take `n` permits if they are all available right now and nobody
+    /// is queued ahead. Either all `n` are taken or none are.
+    pub fn try_acquire_many(&self, n: u64) -> Result<Option<Permit<'_>>> {
+        let _turn = match self.queue.try_join() {
+            Some(turn) => turn,
+            None => return Ok(None),
+        };
+        let got = self.efd.drain_up_to(n)?;
+        if got < n {
+            self.release(got)?;
+            return Ok(None);
+        }
+        Ok(Some(self.permit(n)))
+    }
+
+    /// Take `n` permits, giving up with `Ok(None)` if they don't all become
+    /// available within `timeout`. Any taken before then are given back.
+    pub fn acquire_many_timeout(&self, n: u64, timeout: Duration) -> Result<Option<Permit<'_>>> {
+        self.acquire_until(n, Instant::now().checked_add(timeout))
+    }
+
+    fn acquire_until(&self, n: u64, deadline: Option<Instant>) -> Result<Option<Permit<'_>>> {
+        let _turn = match self.queue.join(deadline) {
+            Some(turn) => turn,
+            None => return Ok(None),
+        };
+        let mut got = 0;
+        let res = loop {
+            match self.efd.drain_up_to(n - got) {
+                Ok(v) => got += v,
+                Err(e) => break Err(e),
+            }
+            if got == n {
+                return Ok(Some(self.permit(n)));
+            }
+            let timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            match self.efd.wait_readable(timeout) {
+                Ok(true) => (),
+                Ok(false) => break Ok(None),
+                Err(e) => break Err(e),
+            }
+        };
+        if got > 0 {
+            self.release(got)?;
+        }
+        res
+    }
+
+    fn permit(&self, count: u64) -> Permit<'_> {
+        Permit { sem: self, count }
+    }
+
+    /// Create another handle to the same semaphore. See
+    /// `EventFD::try_clone`.
+    pub fn try_clone(&self) -> Result<EventSemaphore> {
+        Ok(EventSemaphore {
+            efd: self.efd.try_clone()?,
+            queue: self.queue.clone(),
+        })
+    }
+
+    /// The underlying eventfd.
+    pub fn as_eventfd(&self) -> &EventFD<Semaphore> {
+        &self.efd
+    }
+}
+
+impl From<EventFD<Semaphore>> for EventSemaphore {
+    fn from(efd: EventFD<Semaphore>) -> EventSemaphore {
+        EventSemaphore {
+            efd,
+            queue: Arc::default(),
+        }
+    }
+}
+
+impl AsRawFd for EventSemaphore {
+    fn as_raw_fd(&self) -> RawFd {
+        self.efd.as_raw_fd()
+    }
+}
+
+impl AsFd for EventSemaphore {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.efd.as_fd()
+    }
+}
+
+/// Permits taken from an `EventSemaphore`, released when dropped.
+#[must_use = "the permit is released as soon as it is dropped"]
+pub struct Permit<'a> {
+    sem: &'a EventSemaphore,
+    count: u64,
+}
+
+impl Permit<'_> {
+    /// How many permits this guard holds.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Consume the permits without releasing them, permanently reducing
+    /// the number available.
+    pub fn forget(self) {
+        std::mem::forget(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.count > 0 {
+            let _ = self.sem.release(self.count);
+        }
+    }
+}
+
+/// FIFO queue of acquirers; only the one at the head may take units.
+#[derive(Default)]
+struct Queue {
+    waiters: Mutex<Waiters>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct Waiters {
+    next: u64,
+    queue: VecDeque<u64>,
+}
+
+impl Queue {
+    fn lock(&self) -> MutexGuard<'_, Waiters> {
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Join the back of the queue and wait to reach the head, or leave it
+    /// again and return `None` if `deadline` passes first.
+    fn join(&self, deadline: Option<Instant>) -> Option<Turn<'_>> {
+        let mut w = self.lock();
+        let ticket = w.next;
+        w.next += 1;
+        w.queue.push_back(ticket);
+
+        while w.queue.front() != Some(&ticket) {
+            w = match deadline {
+                None => self.cond.wait(w).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        w.queue.retain(|&t| t != ticket);
+                        return None;
+                    }
+                    self.cond
+                        .wait_timeout(w, left)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+        }
+        Some(Turn { queue: self })
+    }
+
+    /// Take the head of the queue only if nobody is waiting.
+    fn try_join(&self) -> Option<Turn<'_>> {
+        let mut w = self.lock();
+        if !w.queue.is_empty() {
+            return None;
+        }
+        let ticket = w.next;
+        w.next += 1;
+        w.queue.push_back(ticket);
+        Some(Turn { queue: self })
+    }
+}
+
+/// Being at the head of a `Queue`; dropping it lets the next waiter in.
+struct Turn<'a> {
+    queue: &'a Queue,
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        self.queue.lock().queue.pop_front();
+        self.queue.cond.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EventSemaphore, Permit};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_permits() {
+        let sem = EventSemaphore::new(2).unwrap();
+
+        let a = sem.acquire().unwrap();
+        let b = sem.try_acquire().unwrap().unwrap();
+        assert!(sem.try_acquire().unwrap().is_none());
+        assert!(sem
+            .acquire_timeout(Duration::from_millis(10))
+            .unwrap()
+            .is_none());
+
+        drop(a);
+        let c = sem.acquire_timeout(Duration::from_millis(10)).unwrap();
+        assert!(c.is_some());
+
+        b.forget();
+        drop(c);
+        let _d = sem.try_acquire().unwrap().unwrap();
+        assert!(sem.try_acquire().unwrap().is_none());
+
+        sem.release(2).unwrap();
+        let _e = sem.try_acquire().unwrap().unwrap();
+        let _f = sem.try_acquire().unwrap().unwrap();
+        assert!(sem.try_acquire().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_limits_concurrency() {
+        let sem = EventSemaphore::new(3).unwrap();
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let sem = sem.clone();
+                let running = running.clone();
+                let peak = peak.clone();
+                thread::spawn(move || {
+                    let _permit = sem.acquire().unwrap();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn test_acquire_many() {
+        let sem = EventSemaphore::new(5).unwrap();
+
+        let a = sem.acquire_many(3).unwrap();
+        assert_eq!(a.count(), 3);
+        assert!(sem.try_acquire_many(3).unwrap().is_none());
+        // A failed attempt gives back what it took.
+        assert_eq!(sem.as_eventfd().peek().unwrap(), 2);
+        assert!(sem
+            .acquire_many_timeout(3, Duration::from_millis(10))
+            .unwrap()
+            .is_none());
+        assert_eq!(sem.as_eventfd().peek().unwrap(), 2);
+
+        let b = sem.try_acquire_many(2).unwrap().unwrap();
+        assert_eq!(sem.as_eventfd().peek().unwrap(), 0);
+        drop(a);
+        drop(b);
+        assert_eq!(sem.as_eventfd().peek().unwrap(), 5);
+        assert_eq!(sem.acquire_many(0).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_acquire_many_fifo() {
+        let sem = EventSemaphore::new(4).unwrap();
+        let all = sem.acquire_many(4).unwrap();
+
+        // A large acquirer queued first isn't starved by a stream of
+        // single-permit acquirers behind it.
+        let big = {
+            let sem = sem.clone();
+            thread::spawn(move || sem.acquire_many(4).map(Permit::forget))
+        };
+        thread::sleep(Duration::from_millis(20));
+        let small = {
+            let sem = sem.clone();
+            thread::spawn(move || sem.acquire().map(Permit::forget))
+        };
+        thread::sleep(Duration::from_millis(20));
+        assert!(sem.try_acquire().unwrap().is_none());
+
+        drop(all);
+        big.join().unwrap().unwrap();
+        assert_eq!(sem.as_eventfd().peek().unwrap(), 0);
+        sem.release(1).unwrap();
+        small.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+//! Tokio integration, enabled with the `tokio` feature.
+
+use crate::{Counter, EventFD, Flags, Mode};
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::unix::AsyncFd;
+
+/// An EventFD registered with the tokio reactor.
+///
+/// Reads and writes yield to the runtime instead of blocking a worker
+/// thread when the eventfd isn't ready. With the `futures` feature it is
+/// also a `Stream` of read values and a `Sink` for writes.
+pub struct AsyncEventFD<M: Mode = Counter> {
+    inner: AsyncFd<EventFD<M>>,
+    /// Value accepted by `Sink::start_send` but not yet written.
+    #[cfg(feature = "futures")]
+    pending: Option<u64>,
+}
+
+impl AsyncEventFD<Counter> {
+    /// Create a new counter-mode eventfd and register it with the current
+    /// tokio runtime. `EFD_NONBLOCK` is always added to `flags`.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(initval: u32, flags: Flags) -> io::Result<AsyncEventFD> {
+        let efd = EventFD::new(initval, flags | Flags::EFD_NONBLOCK)?;
+        AsyncEventFD::from_eventfd(efd)
+    }
+}
+
+impl<M: Mode> AsyncEventFD<M> {
+    /// Register an existing EventFD with the current tokio runtime.
+    ///
+    /// The fd is switched to non-blocking mode. Because that flag lives in
+    /// the shared file description, any clones of `efd` become
+    /// non-blocking too.
+    pub fn from_eventfd(mut efd: EventFD<M>) -> io::Result<AsyncEventFD<M>> {
+        efd.set_nonblocking(true)?;
+        Ok(AsyncEventFD {
+            inner: AsyncFd::new(efd)?,
+            #[cfg(feature = "futures")]
+            pending: None,
+        })
+    }
+
+    /// Read the current value, waiting until it is non-zero. See
+    /// `EventFD::read` for the semaphore-mode semantics.
+    pub async fn read(&self) -> io::Result<u64> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            match guard.try_io(|inner| Ok(inner.get_ref().read()?)) {
+                Ok(res) => return res,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Add to the current value, waiting while the addition would
+    /// overflow the counter.
+    pub async fn write(&self, val: u64) -> io::Result<()> {
+        loop {
+            let mut guard = self.inner.writable().await?;
+            match guard.try_io(|inner| Ok(inner.get_ref().write(val)?)) {
+                Ok(res) => return res,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Poll for a read, registering `cx` to be woken when the eventfd
+    /// becomes readable.
+    pub fn poll_read(&self, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_read_ready(cx))?;
+            match guard.try_io(|inner| Ok(inner.get_ref().read()?)) {
+                Ok(res) => return Poll::Ready(res),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Poll to add `val`, registering `cx` to be woken when the eventfd
+    /// becomes writable.
+    pub fn poll_write(&self, cx: &mut Context<'_>, val: u64) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_write_ready(cx))?;
+            match guard.try_io(|inner| Ok(inner.get_ref().write(val)?)) {
+                Ok(res) => return Poll::Ready(res),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Borrow the underlying EventFD.
+    pub fn get_ref(&self) -> &EventFD<M> {
+        self.inner.get_ref()
+    }
+
+    /// Deregister from the reactor and return the underlying EventFD.
+    /// The fd is left in non-blocking mode.
+    pub fn into_inner(self) -> EventFD<M> {
+        self.inner.into_inner()
+    }
+}
+
+/// Yields each value read: single units in semaphore mode, accumulated
+/// batches otherwise. The stream never ends.
+#[cfg(feature = "futures")]
+impl<M: Mode> futures_core::Stream for AsyncEventFD<M> {
+    type Item = io::Result<u64>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_read(cx).map(Some)
+    }
+}
+
+/// Adds each item to the counter. At most one value is buffered; it is
+/// written out by `poll_ready`, `poll_flush` or `poll_close`.
+#[cfg(feature = "futures")]
+impl<M: Mode> futures_sink::Sink<u64> for AsyncEventFD<M> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: u64) -> io::Result<()> {
+        debug_assert!(self.pending.is_none(), "start_send without poll_ready");
+        self.pending = Some(item);
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(val) = self.pending {
+            let res = ready!(self.poll_write(cx, val));
+            self.pending = None;
+            res?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<M: Mode> AsRawFd for AsyncEventFD<M> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncEventFD;
+    use crate::{EventFD, Flags};
+
+    #[tokio::test]
+    async fn test_async_read_write() {
+        let efd = AsyncEventFD::new(3, Flags::empty()).unwrap();
+
+        assert_eq!(efd.read().await.unwrap(), 3);
+        efd.write(4).await.unwrap();
+        assert_eq!(efd.read().await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_async_wakeup() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let writer = efd.clone();
+        let efd = AsyncEventFD::from_eventfd(efd).unwrap();
+
+        let task = tokio::spawn(async move { efd.read().await.unwrap() });
+        tokio::task::yield_now().await;
+        writer.write(9).unwrap();
+
+        assert_eq!(task.await.unwrap(), 9);
+    }
+
+    #[cfg(feature = "futures")]
+    #[tokio::test]
+    async fn test_stream_sink() {
+        use futures_util::{SinkExt, StreamExt};
+
+        let sema = EventFD::new_semaphore(3, Flags::empty()).unwrap();
+        let mut sema = AsyncEventFD::from_eventfd(sema).unwrap();
+        let units: Vec<u64> = (&mut sema).take(3).map(Result::unwrap).collect().await;
+        assert_eq!(units, vec![1, 1, 1]);
+
+        let mut counter = AsyncEventFD::new(0, Flags::empty()).unwrap();
+        counter.send(2).await.unwrap();
+        counter.send(5).await.unwrap();
+        assert_eq!(counter.next().await.unwrap().unwrap(), 7);
+    }
+}
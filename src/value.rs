@@ -0,0 +1,58 @@
+//! Conversion between eventfd counter values and the 8-byte buffers that
+//! read(2) and write(2) transfer. Kept free of unsafe code so the value
+//! path can be audited on its own.
+#![forbid(unsafe_code)]
+
+use crate::{Error, Result};
+
+/// Number of bytes every eventfd read or write must transfer.
+pub(crate) const SIZE: usize = 8;
+
+/// The largest value the counter can hold.
+pub(crate) const MAX: u64 = 0xffff_ffff_ffff_fffe;
+
+/// The buffer to write for `val`, rejecting the one value eventfd never
+/// accepts.
+pub(crate) fn encode(val: u64) -> Result<[u8; SIZE]> {
+    if val == u64::MAX {
+        return Err(Error::InvalidValue);
+    }
+    Ok(val.to_ne_bytes())
+}
+
+/// The value carried by a read that transferred `n` bytes into `buf`.
+pub(crate) fn decode(buf: [u8; SIZE], n: usize) -> Result<u64> {
+    match n {
+        SIZE => Ok(u64::from_ne_bytes(buf)),
+        n => Err(Error::ShortRead(n)),
+    }
+}
+
+/// Check that a write transferred the whole buffer.
+pub(crate) fn check_written(n: usize) -> Result<()> {
+    match n {
+        SIZE => Ok(()),
+        n => Err(Error::ShortWrite(n)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for &v in &[0, 1, 0xdead_beef, u64::MAX - 1] {
+            assert_eq!(decode(encode(v).unwrap(), SIZE), Ok(v));
+        }
+        assert_eq!(encode(u64::MAX), Err(Error::InvalidValue));
+    }
+
+    #[test]
+    fn test_byte_counts() {
+        assert_eq!(decode([0; SIZE], 4), Err(Error::ShortRead(4)));
+        assert_eq!(decode([0; SIZE], 0), Err(Error::ShortRead(0)));
+        assert_eq!(check_written(SIZE), Ok(()));
+        assert_eq!(check_written(3), Err(Error::ShortWrite(3)));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::backend::{self, Backend, PollFd, Sys};
+use crate::{Error, Result};
+
+use std::os::unix::io::{AsRawFd, BorrowedFd, RawFd};
+use std::time::{Duration, Instant};
+
+/// Wait until at least one of `fds` is readable, returning the indices of
+/// every readable one, in order. Returns an empty list if `timeout` expires
+/// first; `None` waits forever.
+///
+/// The fds are borrowed with `AsFd::as_fd`, so counter and semaphore
+/// EventFDs can be waited on together. Nothing is read, so the values are
+/// left for the caller to consume. An empty `fds` gives `Error::Os(EINVAL)`.
+pub fn wait_any(fds: &[BorrowedFd<'_>], timeout: Option<Duration>) -> Result<Vec<usize>> {
+    if fds.is_empty() {
+        return Err(Error::Os(libc::EINVAL));
+    }
+    let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+    let mut pfds = poll_fds(fds);
+
+    if poll_until(&mut pfds, deadline, false)? == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(ready_indices(&pfds))
+}
+
+/// Wait until every one of `fds` is readable at the same time. Returns
+/// false if `timeout` expires first; `None` waits forever.
+///
+/// Nothing is read. If another reader empties an eventfd that was already
+/// seen as ready, it is waited for again. As with `wait_any`, an empty
+/// `fds` gives `Error::Os(EINVAL)`.
+pub fn wait_all(fds: &[BorrowedFd<'_>], timeout: Option<Duration>) -> Result<bool> {
+    if fds.is_empty() {
+        return Err(Error::Os(libc::EINVAL));
+    }
+    let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+    let mut pending: Vec<BorrowedFd<'_>> = fds.to_vec();
+
+    loop {
+        // Only wait on the fds that haven't been ready yet, or poll() would
+        // keep returning straight away for the ones that have.
+        while !pending.is_empty() {
+            let mut pfds = poll_fds(&pending);
+            if poll_until(&mut pfds, deadline, false)? == 0 {
+                return Ok(false);
+            }
+            let ready = ready_indices(&pfds);
+            pending = pending
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !ready.contains(i))
+                .map(|(_, fd)| fd)
+                .collect();
+        }
+
+        // Make sure they're all still ready now.
+        let mut pfds = poll_fds(fds);
+        poll_until(&mut pfds, Some(Instant::now()), false)?;
+        let ready = ready_indices(&pfds);
+        if ready.len() == fds.len() {
+            return Ok(true);
+        }
+        pending = fds
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !ready.contains(i))
+            .map(|(_, fd)| *fd)
+            .collect();
+    }
+}
+
+fn poll_fds(fds: &[BorrowedFd<'_>]) -> Vec<PollFd> {
+    fds.iter()
+        .map(|fd| PollFd::new(fd.as_raw_fd(), backend::POLLIN))
+        .collect()
+}
+
+fn ready_indices(pfds: &[PollFd]) -> Vec<usize> {
+    pfds.iter()
+        .enumerate()
+        .filter(|(_, pfd)| pfd.is_ready())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Milliseconds left until `deadline`, rounded up so that poll() never
+/// returns before the deadline has passed. `None` means wait forever.
+pub(crate) fn poll_timeout(deadline: Option<Instant>) -> i32 {
+    match deadline {
+        None => -1,
+        Some(deadline) => {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let ms = left.as_millis() + u128::from(left.subsec_nanos() % 1_000_000 != 0);
+            ms.min(i32::MAX as u128) as i32
+        }
+    }
+}
+
+/// poll() the given fds until one is ready or `deadline` passes, returning
+/// the number of ready fds. If a signal interrupts the wait it is restarted
+/// with whatever time is left, unless `interruptible` is set.
+pub(crate) fn poll_until(
+    fds: &mut [PollFd],
+    deadline: Option<Instant>,
+    interruptible: bool,
+) -> Result<usize> {
+    loop {
+        match Sys::poll(fds, poll_timeout(deadline)) {
+            Err(libc::EINTR) if !interruptible => continue,
+            res => return res.map_err(Error::from_read_errno),
+        }
+    }
+}
+
+/// Wait for `events` on a single fd, returning false on timeout.
+pub(crate) fn poll_fd(
+    fd: RawFd,
+    events: i16,
+    deadline: Option<Instant>,
+    interruptible: bool,
+) -> Result<bool> {
+    let mut fds = [PollFd::new(fd, events)];
+    Ok(poll_until(&mut fds, deadline, interruptible)? > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wait_all, wait_any};
+    use crate::Error;
+    use crate::{EventFD, Flags};
+    use std::os::unix::io::AsFd;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wait_any() {
+        let a = EventFD::new(0, Flags::empty()).unwrap();
+        let b = EventFD::new(0, Flags::empty()).unwrap();
+        let c = EventFD::new(0, Flags::empty()).unwrap();
+        let short = Some(Duration::from_millis(10));
+
+        assert!(wait_any(&[a.as_fd(), b.as_fd(), c.as_fd()], short)
+            .unwrap()
+            .is_empty());
+
+        b.write(1).unwrap();
+        c.write(1).unwrap();
+        assert_eq!(
+            wait_any(&[a.as_fd(), b.as_fd(), c.as_fd()], short).unwrap(),
+            vec![1, 2]
+        );
+        // Waiting doesn't consume.
+        assert_eq!(
+            wait_any(&[a.as_fd(), b.as_fd(), c.as_fd()], None).unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(b.read().unwrap(), 1);
+
+        let writer = a.clone();
+        let t = thread::spawn(move || writer.write(3).unwrap());
+        let mut ready = wait_any(&[a.as_fd(), b.as_fd()], None).unwrap();
+        t.join().unwrap();
+        ready.sort();
+        assert_eq!(ready, vec![0]);
+
+        assert_eq!(wait_any(&[], short), Err(Error::Os(libc::EINVAL)));
+
+        // Counter and semaphore EventFDs can be mixed.
+        let sema = EventFD::new_semaphore(1, Flags::empty()).unwrap();
+        assert_eq!(
+            wait_any(&[a.as_fd(), sema.as_fd()], None).unwrap(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_wait_all() {
+        let a = EventFD::new(0, Flags::empty()).unwrap();
+        let b = EventFD::new(1, Flags::empty()).unwrap();
+        let short = Some(Duration::from_millis(10));
+
+        assert!(!wait_all(&[a.as_fd(), b.as_fd()], short).unwrap());
+        assert_eq!(wait_all(&[], short), Err(Error::Os(libc::EINVAL)));
+
+        let writer = a.clone();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            writer.write(2).unwrap();
+        });
+        assert!(wait_all(&[a.as_fd(), b.as_fd()], Some(Duration::from_secs(5))).unwrap());
+        t.join().unwrap();
+        assert_eq!(a.read().unwrap(), 2);
+        assert_eq!(b.read().unwrap(), 1);
+
+        let sema = EventFD::new_semaphore(2, Flags::empty()).unwrap();
+        b.write(1).unwrap();
+        assert!(wait_all(&[b.as_fd(), sema.as_fd()], short).unwrap());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::backend::{self, PollFd};
+use crate::{wait, Error, EventFD, Mode, Result};
+
+use std::os::unix::io::AsRawFd;
+use std::panic;
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a watcher with a full channel checks for room again. There's
+/// no fd to wait on for that, only for the shutdown request.
+const SEND_RETRY: Duration = Duration::from_millis(10);
+
+/// Handle to a background thread reading an EventFD, created by
+/// `EventFD::watch`.
+///
+/// The thread waits in poll() on both the watched eventfd and a private
+/// shutdown eventfd, so it can be stopped at any time rather than only
+/// between reads. Dropping the handle stops the thread but doesn't wait
+/// for it; use `stop` followed by `join` to collect its final status.
+pub struct Watcher {
+    shutdown: EventFD,
+    thread: Option<thread::JoinHandle<Result<()>>>,
+}
+
+impl Watcher {
+    pub(crate) fn spawn<M: Mode>(
+        efd: &EventFD<M>,
+    ) -> Result<(Watcher, mpsc::Receiver<Result<u64>>)> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let shutdown = EventFD::new(0, crate::Flags::EFD_NONBLOCK)?;
+        let stop = shutdown.try_clone()?;
+        let c = efd.try_clone()?;
+
+        let thread = thread::Builder::new()
+            .name("eventfd-watcher".into())
+            .spawn(move || run(&c, &stop, &tx))
+            .map_err(Error::from)?;
+
+        Ok((
+            Watcher {
+                shutdown,
+                thread: Some(thread),
+            },
+            rx,
+        ))
+    }
+
+    /// Ask the watcher thread to exit. This doesn't wait for it, but the
+    /// thread notices promptly even while it's waiting for room to hand an
+    /// undrained receiver another value; that value is then dropped.
+    pub fn stop(&self) -> Result<()> {
+        self.shutdown.write(1)
+    }
+
+    /// Wait for the watcher thread to exit and return its final status:
+    /// `Ok(())` if it was stopped or its receiver went away, or the read
+    /// error that ended it.
+    pub fn join(mut self) -> Result<()> {
+        match self.thread.take().map(|t| t.join()) {
+            Some(Ok(res)) => res,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            let _ = self.stop();
+        }
+    }
+}
+
+fn run<M: Mode>(
+    efd: &EventFD<M>,
+    shutdown: &EventFD,
+    tx: &mpsc::SyncSender<Result<u64>>,
+) -> Result<()> {
+    loop {
+        let mut fds = [
+            PollFd::new(efd.as_raw_fd(), backend::POLLIN),
+            PollFd::new(shutdown.as_raw_fd(), backend::POLLIN),
+        ];
+        if let Err(e) = wait::poll_until(&mut fds, None, false) {
+            deliver(tx, shutdown, Err(e))?;
+            return Err(e);
+        }
+
+        if fds[1].is_ready() {
+            return Ok(());
+        }
+        if !fds[0].is_ready() {
+            continue;
+        }
+
+        // Another clone may have consumed the value since poll() returned.
+        match efd.try_read() {
+            Ok(Some(v)) => {
+                if !deliver(tx, shutdown, Ok(v))? {
+                    return Ok(());
+                }
+            }
+            Ok(None) => (),
+            Err(e) => {
+                deliver(tx, shutdown, Err(e))?;
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Hand `msg` to the receiver, waiting while the channel is full. Returns
+/// false, dropping `msg`, if the receiver has gone away or a shutdown was
+/// requested in the meantime.
+fn deliver(
+    tx: &mpsc::SyncSender<Result<u64>>,
+    shutdown: &EventFD,
+    mut msg: Result<u64>,
+) -> Result<bool> {
+    loop {
+        match tx.try_send(msg) {
+            Ok(()) => return Ok(true),
+            Err(TrySendError::Disconnected(_)) => return Ok(false),
+            Err(TrySendError::Full(m)) => msg = m,
+        }
+        let retry = Instant::now().checked_add(SEND_RETRY);
+        if wait::poll_fd(shutdown.as_raw_fd(), backend::POLLIN, retry, false)? {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EventFD, Flags};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_stop_blocked() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let (watcher, rx) = efd.watch().unwrap();
+
+        efd.write(3).unwrap();
+        assert_eq!(rx.recv().unwrap().unwrap(), 3);
+
+        // The thread is now waiting on an empty, blocking eventfd.
+        watcher.stop().unwrap();
+        assert!(watcher.join().is_ok());
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_stop_full_channel() {
+        let efd = EventFD::new(0, Flags::empty()).unwrap();
+        let (watcher, rx) = efd.watch().unwrap();
+
+        // The first value fills the channel; the thread is then stuck
+        // trying to hand over the second.
+        efd.write(1).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        efd.write(2).unwrap();
+        thread::sleep(Duration::from_millis(20));
+
+        watcher.stop().unwrap();
+        assert!(watcher.join().is_ok());
+        assert_eq!(rx.recv().unwrap().unwrap(), 1);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_drop_stops() {
+        let efd = EventFD::new(0, Flags::EFD_NONBLOCK).unwrap();
+        let (watcher, rx) = efd.watch().unwrap();
+
+        drop(watcher);
+        assert!(rx.recv().is_err());
+    }
+}